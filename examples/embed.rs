@@ -0,0 +1,53 @@
+/*
+    Shows how to embed the emulator core in another program: no window or keyboard, just a ROM from memory that is
+    stepped a fixed number of cycles before reading back the framebuffer and registers.
+*/
+
+use chip_eight::drivers::{NullDisplay, NullInput, Rom};
+use chip_eight::frames;
+use chip_eight::processor::Processor;
+
+const CYCLES: usize = 20;
+
+fn main() {
+    // Adds 5 and 3, draws the resulting digit in the top left corner and then loops forever
+    #[rustfmt::skip]
+    let program: [u8; 14] = [
+        0x60, 0x05, // 200: V0 = 5
+        0x61, 0x03, // 202: V1 = 3
+        0x80, 0x14, // 204: V0 += V1
+        0xF0, 0x29, // 206: I = font glyph for V0
+        0x62, 0x00, // 208: V2 = 0
+        0xD2, 0x25, // 20A: Draw it at (V2, V2)
+        0x12, 0x0C, // 20C: Loop forever
+    ];
+
+    // The null drivers ignore drawing and never report input
+    let rom = Rom::from_bytes(&program).expect("Program should fit in memory");
+    let mut processor = Processor::new(NullDisplay, NullInput);
+    processor.load_program(rom.bytes());
+
+    for _ in 0..CYCLES {
+        processor
+            .step(None)
+            .expect("Unknown opcodes are skipped by default");
+    }
+
+    for line in frames::to_ascii(processor.framebuffer()).lines().take(5) {
+        println!("{}", &line[..8]);
+    }
+
+    let registers = processor.registers();
+    println!(
+        "After {} cycles: V0={:02X} V1={:02X} VF={:02X} I={:03X} PC={:03X}",
+        CYCLES,
+        registers[0],
+        registers[1],
+        registers[0xF],
+        processor.index(),
+        processor.pc()
+    );
+
+    assert_eq!(registers[0], 8);
+    assert_eq!(processor.pc(), 0x20C);
+}
@@ -0,0 +1,33 @@
+// Runs the built in hello ROM headless and checks that it drew "C8" in the middle of the screen
+
+use chip_eight::drivers::{NullDisplay, NullInput};
+use chip_eight::frames;
+use chip_eight::processor::Processor;
+use chip_eight::roms::{hello_rom_bytes, HELLO_ROM_HALT_ADDRESS};
+
+const EXPECTED_TOP_LEFT: (usize, usize) = (28, 13);
+#[rustfmt::skip]
+const EXPECTED: [&str; 5] = [
+    "####.####",
+    "#....#..#",
+    "#....####",
+    "#....#..#",
+    "####.####",
+];
+
+fn main() {
+    let mut processor = Processor::new(NullDisplay, NullInput);
+    processor.load_program(&hello_rom_bytes());
+
+    // Run until the ROM reaches its halt loop
+    for _ in 0..100 {
+        if processor.step(None).unwrap().pc == HELLO_ROM_HALT_ADDRESS {
+            break;
+        }
+    }
+
+    let expected = frames::from_ascii(&EXPECTED, EXPECTED_TOP_LEFT);
+    frames::assert_frames_eq(processor.framebuffer(), &expected);
+
+    println!("The hello ROM drew the expected framebuffer");
+}
@@ -0,0 +1,317 @@
+use std::str::FromStr;
+
+use chip_eight::{
+    drivers::Layout,
+    processor::{EmptySpritePolicy, UnknownOpcodePolicy},
+    quirks::Variant,
+    CHIP8_MEMORY,
+};
+
+const DEFAULT_COMPARE_CYCLES: usize = 10_000;
+const DEFAULT_STRINGS_MIN: usize = 4;
+const DEFAULT_LOOP_DELAY_SECS: u64 = 5;
+
+const USAGE: &str = "Usage: chip-eight [ROM] [options]
+
+Without a ROM, a small built in demo is run.
+
+Keys:
+    Space                    Pause or resume
+    F2                       Invert the display colors
+    F3                       Toggle the display wait quirk
+    F4                       While paused, run until the current subroutine returns
+    F5                       Save the state to the --save-state file
+    Escape                   Quit
+
+Options:
+    --byteswap               Swap the bytes of each opcode, for ROM dumps stored little endian
+    --entry ADDRESS          Start executing at the hex ADDRESS instead of 0x200, where the ROM is loaded
+    --variant NAME           Interpreter to behave like: chip-8 (default), vip (the original COSMAC VIP) or
+                             chip-48 (what most modern ROMs expect)
+    --layout NAME            Keypad layout: standard (1234/QWER/ASDF/ZXCV), numpad or left-hand
+    --keymap FILE            Use the keys from a keymap file instead of a built in layout
+    --export-keymap FILE     Save the keys in use to a keymap file, e.g. as a starting point for your own
+    --max-keys N             Ignore keypad presses while N keys are already held, like keypad ghosting
+    --timer-hz N             Rate the delay and sound timers count down at (default 60)
+    --refresh-hz N           Experimental: frames per second the display is presented at (default 60, standard)
+    --draw-cost N            Cycles each DXYN takes (default 1), raise it for ROMs that animate too fast
+    --max-frameskip N        Frames in a row that may be skipped to keep up when running slow (default 2)
+    --busy-slowdown          Experimental: run slower while a ROM draws a lot, like the original interpreter
+    --lint-vf                Warn when a ROM reads VF while it may hold a stale flag (heuristic)
+    --run-opcodes LIST       Execute comma separated hex opcodes (e.g. 6005,6103,8014) and print the registers
+    --strings                Print the runs of printable ASCII in the ROM with their addresses and exit. Text in
+                             CHIP-8 ROMs is usually drawn with sprites, so expect misses and false positives
+    --strings-min N          Shortest run --strings prints (default 4)
+    --on-unknown POLICY      What to do with unsupported opcodes: skip (default), halt or panic
+    --on-dxy0 POLICY         What to do with DXY0, a sprite 0 rows high: ignore (default), warn or error.
+                             SUPER-CHIP's 16x16 sprites aren't supported
+    --no-fault-overlay       Only report recovered faults (e.g. a stack overflow) in the terminal, not the window
+    --timing-stats           Print how long frames take every couple of seconds, to diagnose stuttering
+    --loop                   Start the ROM over once it ends, e.g. for an unattended demo
+    --loop-delay N           Seconds to show the end of the ROM for before starting over (default 5)
+    --watch                  Restart with the ROM whenever its file changes, e.g. after assembling it again
+    --seed N                 Seed the random number generator so runs are repeatable
+    --author                 Show every draw as it happens, revealing flicker, instead of once per frame
+    --defer-clear            Keep showing the last frame after a clear until the next draw, reducing flicker
+    --invert                 Start with the display colors inverted (toggle with F2)
+    --debugger               Read debugger commands from the terminal while running, type help for a list
+    --pause-after N          Pause once N instructions were executed (resume with Space)
+    --fade-rate N            Fade unset pixels out over N frames instead of turning them off at once
+    --paint                  While paused (Space), left clicking toggles the pixel under the cursor
+    --dump-frames DIR        Save rendered frames to DIR as numbered PNGs
+    --dump-every N           Only save every Nth frame (default 1)
+    --dump-max N             Stop saving after N frames (default 1000)
+    --load-state FILE        Start from a state saved with --save-state, - reads it from stdin
+    --save-state FILE        Save the state to FILE when pressing F5
+    --snapshot-on-break DIR  Save the state to DIR whenever execution halts, e.g. on a debugger watchpoint, as
+                             break-<Unix time in ms>-<PC>.state
+    --trace FILE             Write every executed instruction to FILE
+    --events FILE            Write events like ROM loads, resets and halts to FILE as JSON lines, - for stdout
+    --compare-trace FILE     Run in lockstep with a reference trace and report the first divergence
+    --compare-quirk NAME     Run the ROM with and without a quirk (shift-vy, jump-vx, display-wait,
+                             clip-sprites, vip-memory) and report where they diverge
+    --cycles N               Number of cycles --compare-quirk runs for (default 10000)
+    --show-config            Print the configuration, quirks and keymap in effect after parsing the options and
+                             exit";
+
+#[derive(Debug)]
+pub struct Config {
+    pub rom_path: Option<String>, // None runs the built in hello ROM
+    pub byteswap: bool,
+    pub entry: Option<usize>,
+    pub variant: Variant,
+    pub layout: Layout,
+    pub keymap_path: Option<String>,
+    pub export_keymap_path: Option<String>,
+    pub max_keys: Option<usize>,
+    pub timer_hz: u32,
+    pub refresh_hz: u32,
+    pub draw_cost: u32,
+    pub max_frameskip: u32,
+    pub busy_slowdown: bool,
+    pub lint_vf: bool,
+    pub run_opcodes: Option<Vec<u16>>,
+    pub strings: bool,
+    pub strings_min: usize,
+    pub on_unknown: UnknownOpcodePolicy,
+    pub on_dxy0: EmptySpritePolicy,
+    pub fault_overlay: bool,
+    pub timing_stats: bool,
+    pub loop_rom: bool,
+    pub loop_delay_secs: u64,
+    pub watch: bool,
+    pub seed: Option<u64>,
+    pub author: bool,
+    pub defer_clear: bool,
+    pub invert: bool,
+    pub fade_frames: Option<u32>,
+    pub pause_after: Option<u64>,
+    pub paint: bool,
+    pub debugger: bool,
+    pub dump_frames_dir: Option<String>,
+    pub dump_every: usize,
+    pub dump_max: usize,
+    pub load_state_path: Option<String>,
+    pub save_state_path: Option<String>,
+    pub snapshot_dir: Option<String>,
+    pub trace_path: Option<String>,
+    pub events_path: Option<String>,
+    pub compare_trace_path: Option<String>,
+    pub compare_quirk: Option<String>,
+    pub cycles: usize,
+    pub show_config: bool,
+}
+
+impl Config {
+    // Builds the configuration from the command line arguments (excluding the program name)
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config {
+            rom_path: None,
+            byteswap: false,
+            entry: None,
+            variant: Variant::Chip8,
+            layout: Layout::Standard,
+            keymap_path: None,
+            export_keymap_path: None,
+            max_keys: None,
+            timer_hz: 60,
+            refresh_hz: 60,
+            draw_cost: 1,
+            max_frameskip: 2,
+            busy_slowdown: false,
+            lint_vf: false,
+            run_opcodes: None,
+            strings: false,
+            strings_min: DEFAULT_STRINGS_MIN,
+            on_unknown: UnknownOpcodePolicy::Skip,
+            on_dxy0: EmptySpritePolicy::Ignore,
+            fault_overlay: true,
+            timing_stats: false,
+            loop_rom: false,
+            loop_delay_secs: DEFAULT_LOOP_DELAY_SECS,
+            watch: false,
+            seed: None,
+            author: false,
+            defer_clear: false,
+            invert: false,
+            fade_frames: None,
+            pause_after: None,
+            paint: false,
+            debugger: false,
+            dump_frames_dir: None,
+            dump_every: 1,
+            dump_max: 1000,
+            load_state_path: None,
+            save_state_path: None,
+            snapshot_dir: None,
+            trace_path: None,
+            events_path: None,
+            compare_trace_path: None,
+            compare_quirk: None,
+            cycles: DEFAULT_COMPARE_CYCLES,
+            show_config: false,
+        };
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--byteswap" => config.byteswap = true,
+                "--entry" => config.entry = Some(address(&arg, args.next())?),
+                "--variant" => config.variant = Variant::from_name(&value(&arg, args.next())?)?,
+                "--layout" => config.layout = Layout::from_name(&value(&arg, args.next())?)?,
+                "--keymap" => config.keymap_path = Some(value(&arg, args.next())?),
+                "--export-keymap" => config.export_keymap_path = Some(value(&arg, args.next())?),
+                "--max-keys" => config.max_keys = Some(positive(&arg, args.next())? as usize),
+                "--timer-hz" => config.timer_hz = positive(&arg, args.next())?,
+                "--refresh-hz" => config.refresh_hz = positive(&arg, args.next())?,
+                "--draw-cost" => config.draw_cost = positive(&arg, args.next())?,
+                "--max-frameskip" => config.max_frameskip = number(&arg, args.next())?,
+                "--busy-slowdown" => config.busy_slowdown = true,
+                "--lint-vf" => config.lint_vf = true,
+                "--run-opcodes" => config.run_opcodes = Some(opcodes(&arg, args.next())?),
+                "--strings" => config.strings = true,
+                "--strings-min" => config.strings_min = positive(&arg, args.next())? as usize,
+                "--on-unknown" => {
+                    config.on_unknown = UnknownOpcodePolicy::from_name(&value(&arg, args.next())?)?
+                }
+                "--on-dxy0" => {
+                    config.on_dxy0 = EmptySpritePolicy::from_name(&value(&arg, args.next())?)?
+                }
+                "--no-fault-overlay" => config.fault_overlay = false,
+                "--timing-stats" => config.timing_stats = true,
+                "--loop" => config.loop_rom = true,
+                "--loop-delay" => config.loop_delay_secs = number(&arg, args.next())?,
+                "--watch" => config.watch = true,
+                "--seed" => config.seed = Some(number(&arg, args.next())?),
+                "--author" => config.author = true,
+                "--defer-clear" => config.defer_clear = true,
+                "--invert" => config.invert = true,
+                "--fade-rate" => config.fade_frames = Some(positive(&arg, args.next())?),
+                "--pause-after" => config.pause_after = Some(number(&arg, args.next())?),
+                "--paint" => config.paint = true,
+                "--debugger" => config.debugger = true,
+                "--dump-frames" => config.dump_frames_dir = Some(value(&arg, args.next())?),
+                "--dump-every" => config.dump_every = positive(&arg, args.next())? as usize,
+                "--dump-max" => config.dump_max = positive(&arg, args.next())? as usize,
+                "--load-state" => config.load_state_path = Some(value(&arg, args.next())?),
+                "--save-state" => config.save_state_path = Some(value(&arg, args.next())?),
+                "--snapshot-on-break" => config.snapshot_dir = Some(value(&arg, args.next())?),
+                "--trace" => config.trace_path = Some(value(&arg, args.next())?),
+                "--events" => config.events_path = Some(value(&arg, args.next())?),
+                "--compare-trace" => config.compare_trace_path = Some(value(&arg, args.next())?),
+                "--compare-quirk" => {
+                    let name = value(&arg, args.next())?;
+                    Variant::Chip8.quirks().toggled(&name)?;
+                    config.compare_quirk = Some(name);
+                }
+                "--cycles" => config.cycles = positive(&arg, args.next())? as usize,
+                "--show-config" => config.show_config = true,
+                "-h" | "--help" => return Err(String::from(USAGE)),
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown option {}\n\n{}", arg, USAGE))
+                }
+                _ => config.rom_path = Some(arg),
+            }
+        }
+
+        if config.debugger && config.load_state_path.as_deref() == Some("-") {
+            return Err(String::from(
+                "--load-state - and --debugger can't both read from stdin",
+            ));
+        }
+
+        if config.watch && config.rom_path.is_none() {
+            return Err(String::from("--watch needs a ROM file to watch"));
+        }
+
+        Ok(config)
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn number<T: FromStr>(flag: &str, arg: Option<String>) -> Result<T, String> {
+    let arg = value(flag, arg)?;
+
+    arg.parse()
+        .map_err(|_| format!("{} expects a number, got {}", flag, arg))
+}
+
+fn opcodes(flag: &str, arg: Option<String>) -> Result<Vec<u16>, String> {
+    let arg = value(flag, arg)?;
+
+    arg.split(',')
+        .map(|op| {
+            let op = op.trim();
+            u16::from_str_radix(op.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("{} expects hex opcodes, got {}", flag, op))
+        })
+        .collect()
+}
+
+// A hex address that a whole instruction fits at
+fn address(flag: &str, arg: Option<String>) -> Result<usize, String> {
+    let arg = value(flag, arg)?;
+
+    match usize::from_str_radix(arg.trim_start_matches("0x"), 16) {
+        Ok(address) if address < CHIP8_MEMORY - 1 => Ok(address),
+        Ok(_) => Err(format!(
+            "{} must be below 0x{:03X}, got {}",
+            flag,
+            CHIP8_MEMORY - 1,
+            arg
+        )),
+        Err(_) => Err(format!("{} expects a hex address, got {}", flag, arg)),
+    }
+}
+
+fn positive(flag: &str, arg: Option<String>) -> Result<u32, String> {
+    match number(flag, arg)? {
+        0 => Err(format!("{} must be greater than 0", flag)),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn entry_must_leave_room_for_an_instruction() {
+        assert_eq!(parse(&["--entry", "FFE"]).unwrap().entry, Some(0xFFE));
+        assert_eq!(parse(&["--entry", "0x300"]).unwrap().entry, Some(0x300));
+
+        for arg in ["FFF", "1000", "FFFFFFFFFFFFFFFF"] {
+            assert_eq!(
+                parse(&["--entry", arg]).err(),
+                Some(format!("--entry must be below 0xFFF, got {}", arg))
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    io::{self, BufRead},
+    ops::Range,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::{trace::parse_hex, CHIP8_MEMORY};
+
+const MAX_POLL_INTERVAL: usize = 0x10000; // Exclusive
+
+pub const HELP: &str = "Debugger commands (numbers are hex):
+    search BYTE...    Find the addresses where the bytes appear in memory, and for a single byte the registers
+                      holding it
+    watch ADDRESS...  Halt after an instruction writes to one of the addresses
+    fill FROM TO BYTE Set the memory from FROM up to, but not including, TO to BYTE
+    poll COUNT        While running, only check for commands every COUNT instructions (default 1). Larger counts
+                      run faster, watchpoints still halt on the instruction that hit them
+    help              Show this message";
+
+/*
+    Reads debugger commands from stdin, one per line. Reading blocks, so it happens on its own thread and the lines
+    are handed to the processor, which runs them between cycles.
+*/
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let (sender, lines) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+
+        println!("Debugger console ready, type help for a list of commands");
+
+        Console { lines }
+    }
+
+    // A console fed by the test instead of stdin
+    #[cfg(test)]
+    pub(crate) fn from_lines(lines: Receiver<String>) -> Self {
+        Console { lines }
+    }
+
+    // Returns the next command typed since the last poll, without waiting for one
+    pub fn poll(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+}
+
+pub enum Command {
+    Search(Vec<u8>),
+    Watch(Vec<usize>),
+    Fill(Range<usize>, u8),
+    Poll(u32),
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("search") => {
+                let pattern = hex_values("search", "bytes", words, 0x100)?;
+
+                Ok(Command::Search(
+                    pattern.iter().map(|&byte| byte as u8).collect(),
+                ))
+            }
+            Some("watch") => {
+                let addresses = hex_values("watch", "addresses", words, CHIP8_MEMORY)?;
+
+                Ok(Command::Watch(addresses))
+            }
+            Some("fill") => match hex_values("fill", "values", words, CHIP8_MEMORY + 1)?[..] {
+                [start, end, byte] if start < end && byte <= 0xFF => {
+                    Ok(Command::Fill(start..end, byte as u8))
+                }
+                [_, _, byte] if byte <= 0xFF => Err(String::from(
+                    "fill expects FROM to be below TO, both up to 0x1000",
+                )),
+                _ => Err(String::from("fill expects FROM, TO and a byte")),
+            },
+            Some("poll") => match hex_values("poll", "count", words, MAX_POLL_INTERVAL)?[..] {
+                [0] => Err(String::from("poll expects a count greater than 0")),
+                [count] => Ok(Command::Poll(count as u32)),
+                _ => Err(String::from("poll expects a single count")),
+            },
+            Some("help") => Ok(Command::Help),
+            Some(command) => Err(format!("Unknown command {}, type help for a list", command)),
+            None => Err(String::from("Type help for a list of commands")),
+        }
+    }
+}
+
+// Parses one or more hex arguments below the limit
+fn hex_values<'a>(
+    command: &str,
+    kind: &str,
+    words: impl Iterator<Item = &'a str>,
+    limit: usize,
+) -> Result<Vec<usize>, String> {
+    let values = words
+        .map(|word| match parse_hex(word) {
+            Some(value) if (value as usize) < limit => Ok(value as usize),
+            _ => Err(format!("{} expects hex {}, got {}", command, kind, word)),
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    if values.is_empty() {
+        return Err(format!("{} expects at least one of its {}", command, kind));
+    }
+
+    Ok(values)
+}
+
+// Addresses at which the pattern starts in memory
+pub fn search(memory: &[u8], pattern: &[u8]) -> Vec<usize> {
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(address, _)| address)
+        .collect()
+}
+
+// Formats addresses for the console, e.g. "0x200 0x3F0"
+pub fn format_addresses(addresses: &[usize]) -> String {
+    if addresses.is_empty() {
+        return String::from("none");
+    }
+
+    let addresses: Vec<String> = addresses
+        .iter()
+        .map(|address| format!("0x{:03X}", address))
+        .collect();
+
+    addresses.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_takes_a_range_and_a_byte() {
+        assert!(matches!(
+            Command::parse("fill 300 1000 FF"),
+            Ok(Command::Fill(range, 0xFF)) if range == (0x300..0x1000)
+        ));
+    }
+
+    #[test]
+    fn fill_rejects_an_empty_or_reversed_range() {
+        for line in ["fill 300 300 0", "fill 301 300 0"] {
+            assert_eq!(
+                Command::parse(line).err().unwrap(),
+                "fill expects FROM to be below TO, both up to 0x1000"
+            );
+        }
+    }
+
+    #[test]
+    fn fill_rejects_a_range_past_the_end_of_memory() {
+        assert_eq!(
+            Command::parse("fill 300 1001 0").err().unwrap(),
+            "fill expects hex values, got 1001"
+        );
+    }
+
+    #[test]
+    fn fill_rejects_values_that_are_not_a_byte() {
+        assert_eq!(
+            Command::parse("fill 300 310 100").err().unwrap(),
+            "fill expects FROM, TO and a byte"
+        );
+    }
+}
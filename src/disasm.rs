@@ -0,0 +1,86 @@
+use crate::quirks::Quirks;
+
+/*
+    Turns opcodes into the assembly they were written as, using the mnemonics of Cowgod's technical reference
+    (e.g. 6A05 is LD VA, 0x05), along with the operands the instruction reads so their values can be shown next to
+    it. Quirks that change which registers an instruction uses change its disassembly too. Opcodes this emulator
+    doesn't support can't be disassembled.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Register(usize), // VX
+    Index,           // I
+}
+
+pub fn disassemble(opcode: u16, quirks: &Quirks) -> Option<(String, Vec<Operand>)> {
+    let nibbles = (
+        (opcode >> 12) as u8,
+        (opcode >> 8 & 0xF) as usize,
+        (opcode >> 4 & 0xF) as usize,
+        (opcode & 0xF) as u8,
+    );
+
+    let nnn = opcode & 0x0FFF;
+    let nn = opcode & 0x00FF;
+    let (x, y) = (nibbles.1, nibbles.2);
+    let (vx, vy) = (Operand::Register(x), Operand::Register(y));
+
+    let (mnemonic, operands) = match nibbles {
+        (0x0, 0x0, 0xE, 0x0) => (String::from("CLS"), vec![]),
+        (0x0, 0x0, 0xE, 0xE) => (String::from("RET"), vec![]),
+        (0x1, _, _, _) => (format!("JP 0x{:03X}", nnn), vec![]),
+        (0x2, _, _, _) => (format!("CALL 0x{:03X}", nnn), vec![]),
+        (0x3, _, _, _) => (format!("SE V{:X}, 0x{:02X}", x, nn), vec![vx]),
+        (0x4, _, _, _) => (format!("SNE V{:X}, 0x{:02X}", x, nn), vec![vx]),
+        (0x5, _, _, 0x0) => (format!("SE V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x6, _, _, _) => (format!("LD V{:X}, 0x{:02X}", x, nn), vec![]),
+        (0x7, _, _, _) => (format!("ADD V{:X}, 0x{:02X}", x, nn), vec![vx]),
+        (0x8, _, _, 0x0) => (format!("LD V{:X}, V{:X}", x, y), vec![vy]),
+        (0x8, _, _, 0x1) => (format!("OR V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x2) => (format!("AND V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x3) => (format!("XOR V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x4) => (format!("ADD V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x5) => (format!("SUB V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x7) => (format!("SUBN V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0x8, _, _, 0x6 | 0xE) => {
+            let name = if nibbles.3 == 0x6 { "SHR" } else { "SHL" };
+
+            if quirks.shift_uses_vy {
+                (format!("{} V{:X}, V{:X}", name, x, y), vec![vy])
+            } else {
+                (format!("{} V{:X}", name, x), vec![vx])
+            }
+        }
+        (0x9, _, _, 0x0) => (format!("SNE V{:X}, V{:X}", x, y), vec![vx, vy]),
+        (0xA, _, _, _) => (format!("LD I, 0x{:03X}", nnn), vec![]),
+        (0xB, _, _, _) if quirks.jump_uses_vx => (format!("JP V{:X}, 0x{:03X}", x, nnn), vec![vx]),
+        (0xB, _, _, _) => (format!("JP V0, 0x{:03X}", nnn), vec![Operand::Register(0)]),
+        (0xC, _, _, _) => (format!("RND V{:X}, 0x{:02X}", x, nn), vec![]),
+        (0xD, _, _, n) => (
+            format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            vec![vx, vy, Operand::Index],
+        ),
+        (0xE, _, 0x9, 0xE) => (format!("SKP V{:X}", x), vec![vx]),
+        (0xE, _, 0xA, 0x1) => (format!("SKNP V{:X}", x), vec![vx]),
+        (0xF, _, 0x0, 0x7) => (format!("LD V{:X}, DT", x), vec![]),
+        (0xF, _, 0x0, 0xA) => (format!("LD V{:X}, K", x), vec![]),
+        (0xF, _, 0x1, 0x5) => (format!("LD DT, V{:X}", x), vec![vx]),
+        (0xF, _, 0x1, 0x8) => (format!("LD ST, V{:X}", x), vec![vx]),
+        (0xF, _, 0x1, 0xE) => (format!("ADD I, V{:X}", x), vec![vx, Operand::Index]),
+        (0xF, _, 0x2, 0x9) => (format!("LD F, V{:X}", x), vec![vx]),
+        (0xF, _, 0x3, 0x3) => (format!("LD B, V{:X}", x), vec![vx, Operand::Index]),
+        (0xF, _, 0x5, 0x5) => (format!("LD [I], V{:X}", x), vec![Operand::Index]),
+        (0xF, _, 0x6, 0x5) => (format!("LD V{:X}, [I]", x), vec![Operand::Index]),
+        _ => return None,
+    };
+
+    let mut unique = Vec::with_capacity(operands.len());
+    for operand in operands {
+        if !unique.contains(&operand) {
+            unique.push(operand);
+        }
+    }
+
+    Some((mnemonic, unique))
+}
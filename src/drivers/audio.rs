@@ -0,0 +1,69 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+const BEEP_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+
+// Where the processor sends the beep of the sound timer
+pub trait AudioBackend {
+    fn set_beeping(&mut self, beeping: bool);
+}
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { VOLUME } else { -VOLUME };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+// Beeps with a square wave through the default audio device
+pub struct AudioDriver {
+    device: AudioDevice<SquareWave>,
+    beeping: bool,
+}
+
+impl AudioDriver {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+        let audio_subsystem = sdl_context.audio()?;
+
+        let spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &spec, |spec| SquareWave {
+            phase_inc: BEEP_HZ / spec.freq as f32,
+            phase: 0.0,
+        })?;
+
+        Ok(AudioDriver {
+            device,
+            beeping: false,
+        })
+    }
+}
+
+impl AudioBackend for AudioDriver {
+    // The device starts out paused, it's only resumed and paused again when the beep actually starts or stops
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping == self.beeping {
+            return;
+        }
+
+        if beeping {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.beeping = beeping;
+    }
+}
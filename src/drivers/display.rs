@@ -1,29 +1,71 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
 use sdl2::pixels;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::{self};
 
+use super::screenshot::FrameDump;
+
 const CHIP8_HEIGHT: usize = 32;
 const CHIP8_WIDTH: usize = 64;
 
+const WINDOW_TITLE: &str = "rust-sdl2_gfx: draw line & FPSManager";
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
 const SCALE_FACTOR: u32 = 20;
 const SCREEN_WIDTH: u32 = (CHIP8_WIDTH as u32) * SCALE_FACTOR;
 const SCREEN_HEIGHT: u32 = (CHIP8_HEIGHT as u32) * SCALE_FACTOR;
 
+/*
+    Where the processor presents its framebuffer. Only drawing is required, the other methods back interactive
+    features and do nothing unless a backend supports them.
+*/
+pub trait DisplayBackend {
+    fn draw(&mut self, pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]);
+
+    // Translates window coordinates (e.g. of a mouse click) to the CHIP-8 pixel underneath them
+    fn pixel_at(&self, _x: i32, _y: i32) -> Option<(usize, usize)> {
+        None
+    }
+
+    fn toggle_inverted(&mut self) {}
+
+    // Briefly shows a short piece of feedback to the user, e.g. after toggling a setting
+    fn show_message(&mut self, _message: &str) {}
+
+    // Whether the backend wants to be drawn at the end of the frame even though the framebuffer didn't change
+    fn needs_refresh(&self) -> bool {
+        false
+    }
+
+    // Called at the end of every frame, also when the frame isn't drawn, e.g. to advance effects that run per frame
+    fn frame_ended(&mut self) {}
+
+    // Hands over the path of the image the last draw saved to disk, if it saved one
+    fn take_saved_frame(&mut self) -> Option<PathBuf> {
+        None
+    }
+}
+
 pub struct DisplayDriver {
     canvas: Canvas<Window>,
+    frame_dump: Option<FrameDump>,
+    saved_frame: Option<PathBuf>, // The image the frame dump saved for the last draw, until it's taken
+    inverted: bool, // Render unset pixels in the foreground color and set pixels in the background color
+    message_expiry: Option<Instant>, // When the message shown in the title bar should be cleared
+    fade_step: Option<u8>, // How much brightness an unset pixel loses per frame, when fading is enabled
+    brightness: [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT], // Of each pixel as rendered, 255 is fully lit
+    ended_frames: u32, // Frames that ended since the last draw, unset pixels fade a step for each of them
 }
 
 impl DisplayDriver {
     pub fn new(sdl_context: &sdl2::Sdl) -> Self {
         let video_subsys = sdl_context.video().unwrap();
         let window = video_subsys
-            .window(
-                "rust-sdl2_gfx: draw line & FPSManager",
-                SCREEN_WIDTH,
-                SCREEN_HEIGHT,
-            )
+            .window(WINDOW_TITLE, SCREEN_WIDTH, SCREEN_HEIGHT)
             .position_centered()
             .opengl()
             .build()
@@ -35,16 +77,54 @@ impl DisplayDriver {
         canvas.clear();
         canvas.present();
 
-        DisplayDriver { canvas: canvas }
+        DisplayDriver {
+            canvas,
+            frame_dump: None,
+            saved_frame: None,
+            inverted: false,
+            message_expiry: None,
+            fade_step: None,
+            brightness: [[0; CHIP8_WIDTH]; CHIP8_HEIGHT],
+            ended_frames: 0,
+        }
+    }
+
+    // Only changes how pixels are rendered, the emulated framebuffer is left alone
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
+    /*
+        Makes pixels fade out over the given number of frames after they are unset instead of disappearing at once,
+        like the phosphor of a CRT. This hides most of the flicker of sprites being erased and redrawn. Fades longer
+        than 255 frames are cut short, since a pixel can only lose one brightness level per frame.
+    */
+    pub fn set_fade_frames(&mut self, frames: u32) {
+        self.fade_step = Some(fade_step(frames));
+    }
+
+    // Saves rendered frames as PNGs from now on
+    pub fn set_frame_dump(&mut self, frame_dump: FrameDump) {
+        self.frame_dump = Some(frame_dump);
+    }
+}
+
+impl DisplayBackend for DisplayDriver {
+    fn draw(&mut self, pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]) {
+        update_brightness(
+            &mut self.brightness,
+            pixels,
+            self.fade_step,
+            self.ended_frames,
+        );
+        self.ended_frames = 0;
+
+        for (y, row) in self.brightness.iter().enumerate() {
+            for (x, &brightness) in row.iter().enumerate() {
                 let x = (x as u32) * SCALE_FACTOR;
                 let y = (y as u32) * SCALE_FACTOR;
 
-                self.canvas.set_draw_color(color(col));
+                self.canvas.set_draw_color(shade(brightness, self.inverted));
                 let _ = self.canvas.fill_rect(Rect::new(
                     x as i32,
                     y as i32,
@@ -54,13 +134,213 @@ impl DisplayDriver {
             }
         }
         self.canvas.present();
+
+        if self
+            .message_expiry
+            .is_some_and(|expiry| Instant::now() >= expiry)
+        {
+            let _ = self.canvas.window_mut().set_title(WINDOW_TITLE);
+            self.message_expiry = None;
+        }
+
+        if let Some(frame_dump) = &mut self.frame_dump {
+            // Dump what was rendered, so pixels that are fading out keep their shade
+            let inverted = self.inverted;
+            let result = frame_dump.capture(&self.brightness, |brightness| {
+                let rgb = shade(brightness, inverted);
+                (rgb.r, rgb.g, rgb.b)
+            });
+
+            match result {
+                Ok(saved_frame) => self.saved_frame = saved_frame,
+                Err(err) => {
+                    println!("Failed to dump frame, frame dumping disabled: {}", err);
+                    self.frame_dump = None;
+                }
+            }
+        }
     }
+
+    fn pixel_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+
+        let col = x as usize / SCALE_FACTOR as usize;
+        let row = y as usize / SCALE_FACTOR as usize;
+
+        if col >= CHIP8_WIDTH || row >= CHIP8_HEIGHT {
+            return None;
+        }
+
+        Some((col, row))
+    }
+
+    fn toggle_inverted(&mut self) {
+        self.inverted = !self.inverted;
+    }
+
+    // Keep drawing while pixels are still fading out
+    fn needs_refresh(&self) -> bool {
+        self.fade_step.is_some() && self.brightness.iter().flatten().any(|&b| b > 0 && b < 255)
+    }
+
+    fn frame_ended(&mut self) {
+        self.ended_frames = self.ended_frames.saturating_add(1);
+    }
+
+    fn take_saved_frame(&mut self) -> Option<PathBuf> {
+        self.saved_frame.take()
+    }
+
+    // Shows the message in the title bar, it's cleared by the first frame drawn after a couple of seconds
+    fn show_message(&mut self, message: &str) {
+        let title = format!("{} - {}", WINDOW_TITLE, message);
+        let _ = self.canvas.window_mut().set_title(&title);
+
+        self.message_expiry = Some(Instant::now() + MESSAGE_DURATION);
+    }
+}
+
+/*
+   The smallest brightness lost per frame for a pixel to go from fully lit to off within the given number of frames.
+   It's a whole level per frame, so e.g. a fade over 60 frames is done after 51.
+*/
+fn fade_step(frames: u32) -> u8 {
+    255u32.div_ceil(frames).min(255) as u8
 }
 
-fn color(value: u8) -> pixels::Color {
-    if value == 0 {
-        pixels::Color::RGB(0, 0, 0)
+fn fade(brightness: u8, step: u8) -> u8 {
+    brightness.saturating_sub(step)
+}
+
+/*
+   Brings the brightness of each pixel up to date with the framebuffer. Set pixels are fully lit, unset ones go out
+   straight away, or lose a step for each of the frames that ended since the last draw when fading.
+*/
+fn update_brightness(
+    brightness: &mut [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    fade_step: Option<u8>,
+    ended_frames: u32,
+) {
+    let loss = fade_step.map(|step| (step as u32).saturating_mul(ended_frames).min(255) as u8);
+
+    for (levels, row) in brightness.iter_mut().zip(pixels.iter()) {
+        for (level, &pixel) in levels.iter_mut().zip(row.iter()) {
+            *level = match (pixel, loss) {
+                (0, Some(loss)) => fade(*level, loss),
+                (0, None) => 0,
+                _ => 255,
+            };
+        }
+    }
+}
+
+// Blends between the background and foreground colors, 0 being the background and 255 the foreground
+fn shade(brightness: u8, inverted: bool) -> pixels::Color {
+    let brightness = if inverted {
+        255 - brightness
     } else {
-        pixels::Color::RGB(250, 250, 250)
+        brightness
+    };
+    let level = (brightness as u32 * 250 / 255) as u8;
+
+    pixels::Color::RGB(level, level, level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The frames it takes a fully lit pixel to go out
+    fn frames_to_go_out(frames: u32) -> u32 {
+        let step = fade_step(frames);
+        let mut brightness = 255;
+        let mut count = 0;
+
+        while brightness > 0 {
+            brightness = fade(brightness, step);
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn pixels_go_out_in_the_given_number_of_frames_when_255_divides_evenly() {
+        for frames in [1, 3, 5, 15, 17, 51, 85, 255] {
+            assert_eq!(frames_to_go_out(frames), frames);
+        }
+    }
+
+    #[test]
+    fn pixels_go_out_within_the_given_number_of_frames_as_slowly_as_possible() {
+        for frames in 1..=255 {
+            let step = fade_step(frames) as u32;
+            assert!(frames_to_go_out(frames) <= frames);
+            assert!(step == 1 || 255u32.div_ceil(step - 1) > frames);
+        }
+        assert_eq!(frames_to_go_out(60), 51);
+    }
+
+    #[test]
+    fn fades_longer_than_255_frames_are_cut_short() {
+        assert_eq!(fade_step(256), 1);
+        assert_eq!(fade_step(u32::MAX), 1);
+        assert_eq!(frames_to_go_out(1000), 255);
+    }
+
+    #[test]
+    fn fade_stops_at_off() {
+        assert_eq!(fade(10, 85), 0);
+        assert_eq!(fade(0, 1), 0);
+        assert_eq!(fade(255, 85), 170);
+    }
+
+    #[test]
+    fn shade_blends_between_background_and_foreground() {
+        assert_eq!(shade(0, false), pixels::Color::RGB(0, 0, 0));
+        assert_eq!(shade(255, false), pixels::Color::RGB(250, 250, 250));
+        assert_eq!(shade(255, true), pixels::Color::RGB(0, 0, 0));
+        assert_eq!(shade(0, true), pixels::Color::RGB(250, 250, 250));
+    }
+
+    #[test]
+    fn unset_pixels_only_fade_when_a_frame_ended() {
+        let mut brightness = [[0; CHIP8_WIDTH]; CHIP8_HEIGHT];
+        let mut pixels = [[0; CHIP8_WIDTH]; CHIP8_HEIGHT];
+        pixels[0][0] = 1;
+        update_brightness(&mut brightness, &pixels, Some(85), 1);
+        assert_eq!(brightness[0][0], 255);
+
+        // Draws within a frame, e.g. when presenting every draw, keep the pixel at the same level
+        pixels[0][0] = 0;
+        update_brightness(&mut brightness, &pixels, Some(85), 0);
+        update_brightness(&mut brightness, &pixels, Some(85), 0);
+        assert_eq!(brightness[0][0], 255);
+
+        update_brightness(&mut brightness, &pixels, Some(85), 1);
+        assert_eq!(brightness[0][0], 170);
+    }
+
+    #[test]
+    fn frames_that_were_not_drawn_still_fade() {
+        let mut brightness = [[255; CHIP8_WIDTH]; CHIP8_HEIGHT];
+        let pixels = [[0; CHIP8_WIDTH]; CHIP8_HEIGHT];
+
+        update_brightness(&mut brightness, &pixels, Some(85), 2);
+        assert_eq!(brightness[0][0], 85);
+
+        update_brightness(&mut brightness, &pixels, Some(85), u32::MAX);
+        assert_eq!(brightness[0][0], 0);
+    }
+
+    #[test]
+    fn unset_pixels_go_out_at_once_without_fading() {
+        let mut brightness = [[255; CHIP8_WIDTH]; CHIP8_HEIGHT];
+        let pixels = [[0; CHIP8_WIDTH]; CHIP8_HEIGHT];
+
+        update_brightness(&mut brightness, &pixels, None, 0);
+        assert_eq!(brightness[0][0], 0);
     }
 }
@@ -1,28 +1,322 @@
+use std::{fmt, fs};
+
 use sdl2::event;
 use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+
+pub enum Input {
+    Key(u8),                  // A CHIP-8 keypad key
+    Pause,                    // Toggle pausing execution
+    ToggleInvert,             // Swap the display's foreground and background colors
+    ToggleDisplayWait,        // Flip the display wait quirk
+    StepOut,                  // While paused, run until the current subroutine returns
+    SaveState,                // Save the machine state to a file
+    Click { x: i32, y: i32 }, // Left mouse button pressed at window coordinates
+}
+
+// Where the processor gets keypad presses and emulator controls from
+pub trait InputBackend {
+    fn last_input(&mut self) -> Option<Input>;
+
+    // Whether a CHIP-8 keypad key is currently held down
+    fn is_pressed(&self, _keycode: u8) -> bool {
+        false
+    }
+
+    /*
+       The last keypad key that went down since the latch was taken, so a press can be consumed exactly once (e.g. by
+       FX0A) even when other input arrives in the same poll. Holding a key down doesn't latch it again.
+    */
+    fn peek_latched_key(&self) -> Option<u8> {
+        None
+    }
+
+    fn take_latched_key(&mut self) -> Option<u8> {
+        None
+    }
+
+    // Whether the window has the keyboard focus, backends without a window always do
+    fn has_focus(&self) -> bool {
+        true
+    }
+}
+
+// Physical keys for each position of the CHIP-8 keypad, row by row
+type KeyLayout = [[Scancode; 4]; 4];
+
+const CHIP8_KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// The keypad's shape on the left of the keyboard: 1234/QWER/ASDF/ZXCV
+const STANDARD_LAYOUT: KeyLayout = [
+    [
+        Scancode::Num1,
+        Scancode::Num2,
+        Scancode::Num3,
+        Scancode::Num4,
+    ],
+    [Scancode::Q, Scancode::W, Scancode::E, Scancode::R],
+    [Scancode::A, Scancode::S, Scancode::D, Scancode::F],
+    [Scancode::Z, Scancode::X, Scancode::C, Scancode::V],
+];
+
+// Digits on the numeric keypad keys with the same label, A-F on the surrounding operator keys
+const NUMPAD_LAYOUT: KeyLayout = [
+    [
+        Scancode::Kp1,
+        Scancode::Kp2,
+        Scancode::Kp3,
+        Scancode::KpDivide,
+    ],
+    [
+        Scancode::Kp4,
+        Scancode::Kp5,
+        Scancode::Kp6,
+        Scancode::KpMultiply,
+    ],
+    [
+        Scancode::Kp7,
+        Scancode::Kp8,
+        Scancode::Kp9,
+        Scancode::KpMinus,
+    ],
+    [
+        Scancode::KpPeriod,
+        Scancode::Kp0,
+        Scancode::KpEnter,
+        Scancode::KpPlus,
+    ],
+];
+
+// The keypad's shape on the right of the keyboard (7890/UIOP/JKL;/M,./), for players with the mouse in their left hand
+const LEFT_HAND_LAYOUT: KeyLayout = [
+    [
+        Scancode::Num7,
+        Scancode::Num8,
+        Scancode::Num9,
+        Scancode::Num0,
+    ],
+    [Scancode::U, Scancode::I, Scancode::O, Scancode::P],
+    [Scancode::J, Scancode::K, Scancode::L, Scancode::Semicolon],
+    [
+        Scancode::M,
+        Scancode::Comma,
+        Scancode::Period,
+        Scancode::Slash,
+    ],
+];
+
+// Keys taken by the emulator's own controls in handle_event, which a keymap can't map to the keypad
+const CONTROL_KEYS: [Scancode; 6] = [
+    Scancode::Escape,
+    Scancode::Space,
+    Scancode::F2,
+    Scancode::F3,
+    Scancode::F4,
+    Scancode::F5,
+];
+
+#[derive(Clone, Copy, Debug)]
+pub enum Layout {
+    Standard,
+    Numpad,
+    LeftHand,
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "standard" => Ok(Layout::Standard),
+            "numpad" => Ok(Layout::Numpad),
+            "left-hand" => Ok(Layout::LeftHand),
+            _ => Err(format!(
+                "Unknown keypad layout {}, expected one of: standard, numpad, left-hand",
+                name
+            )),
+        }
+    }
+
+    fn keys(&self) -> KeyLayout {
+        match self {
+            Layout::Standard => STANDARD_LAYOUT,
+            Layout::Numpad => NUMPAD_LAYOUT,
+            Layout::LeftHand => LEFT_HAND_LAYOUT,
+        }
+    }
+}
+
+// Which physical key plays each CHIP-8 key, from a built in layout or a keymap file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keymap {
+    keys: KeyLayout,
+}
+
+impl Keymap {
+    pub fn from_layout(layout: Layout) -> Self {
+        Keymap {
+            keys: layout.keys(),
+        }
+    }
+
+    /*
+       Keymap files say which key plays each CHIP-8 key, one per line as the hex key, "=" and the SDL name of the
+       physical key, e.g. for the standard layout:
+
+           1 = 1
+           C = 4
+           A = Z
+           0 = X
+
+       Lines starting with # are comments. Every key from 0 to F has to be mapped exactly once, each to a different
+       physical key, and the emulator's controls (Escape, Space and F2 to F5) can't be used.
+    */
+    pub fn import(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read keymap {}: {}", path, err))?;
+
+        let keys = parse_keymap(&text).map_err(|err| format!("Keymap {}: {}", path, err))?;
+
+        Ok(Keymap { keys })
+    }
+
+    pub fn export(&self, path: &str) -> Result<(), String> {
+        fs::write(path, format_keymap(&self.keys))
+            .map_err(|err| format!("Could not write keymap {}: {}", path, err))
+    }
+}
+
+// The keypad row by row, e.g. "1=1, 2=2, 3=3, C=4 | 4=Q, ..." for the standard layout
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<String> = CHIP8_KEYPAD
+            .iter()
+            .zip(&self.keys)
+            .map(|(keycodes, keys)| {
+                let pairs: Vec<String> = keycodes
+                    .iter()
+                    .zip(keys)
+                    .map(|(keycode, key)| format!("{:X}={}", keycode, key.name()))
+                    .collect();
+                pairs.join(", ")
+            })
+            .collect();
+
+        write!(f, "{}", rows.join(" | "))
+    }
+}
+
+// Which keypad keys are held, independent of where the key events come from
+#[derive(Default)]
+struct Keypad {
+    pressed: [bool; 16], // Keypad keys currently held down, indexed by CHIP-8 keycode
+    latched: Option<u8>, // The last keypad key newly pressed, until it's taken
+    max_simultaneous_keys: Option<usize>,
+}
+
+impl Keypad {
+    /*
+       Marks a keypad key as held and latches it, unless it already was held or that would exceed the simultaneous
+       key limit. Returns whether it was newly pressed, so key repeats of a held key are ignored.
+    */
+    fn press(&mut self, keycode: u8) -> bool {
+        let held = self.pressed.iter().filter(|&&pressed| pressed).count();
+
+        if self.pressed[keycode as usize] {
+            return false;
+        }
+
+        if let Some(max) = self.max_simultaneous_keys {
+            if held >= max {
+                return false;
+            }
+        }
+
+        self.pressed[keycode as usize] = true;
+        self.latched = Some(keycode);
+        true
+    }
+
+    fn release(&mut self, keycode: u8) {
+        self.pressed[keycode as usize] = false;
+    }
+
+    fn is_pressed(&self, keycode: u8) -> bool {
+        self.pressed.get(keycode as usize).copied().unwrap_or(false)
+    }
+}
 
 pub struct InputDriver {
     event_pump: sdl2::EventPump,
+    layout: KeyLayout,
+    keypad: Keypad,
+    focused: bool,
 }
 
 impl InputDriver {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+    pub fn new(sdl_context: &sdl2::Sdl, keymap: Keymap) -> Self {
         let event_pump = sdl_context.event_pump().unwrap();
 
-        InputDriver { event_pump }
+        InputDriver {
+            event_pump,
+            layout: keymap.keys,
+            keypad: Keypad::default(),
+            focused: true,
+        }
     }
 
     /*
-       Gets the last input to the program. If the last input was an escape key or quit event, the program will exit.
-       Otherwise, it looks to see the last key key pressed and attempt to map it to the corresponding CHIP-8 keycode.
+       Caps how many keypad keys can be held at once. Presses beyond the cap are ignored until a held key is
+       released, like the ghosting of the original keypad matrix. Unlimited by default.
     */
-    pub fn last_input(&mut self) -> Option<u8> {
-        let last_event = match self.event_pump.poll_iter().last() {
-            Some(event) => event,
-            _ => return None,
-        };
+    pub fn set_max_simultaneous_keys(&mut self, max: usize) {
+        self.keypad.max_simultaneous_keys = Some(max);
+    }
+}
+
+impl InputBackend for InputDriver {
+    /*
+       Gets the last input to the program. If an escape key or quit event arrived, the program will exit.
+       Otherwise, it looks to see the last key key pressed and attempt to map it to the corresponding CHIP-8 keycode,
+       or to one of the emulator's own controls. Every pending event updates the keypad state along the way.
+    */
+    fn last_input(&mut self) -> Option<Input> {
+        let events: Vec<event::Event> = self.event_pump.poll_iter().collect();
+
+        let mut last_input = None;
+        for event in events {
+            if let Some(input) = self.handle_event(event) {
+                last_input = Some(input);
+            }
+        }
+
+        last_input
+    }
 
-        match last_event {
+    fn is_pressed(&self, keycode: u8) -> bool {
+        self.keypad.is_pressed(keycode)
+    }
+
+    fn peek_latched_key(&self) -> Option<u8> {
+        self.keypad.latched
+    }
+
+    fn take_latched_key(&mut self) -> Option<u8> {
+        self.keypad.latched.take()
+    }
+
+    fn has_focus(&self) -> bool {
+        self.focused
+    }
+}
+
+impl InputDriver {
+    // The emulator's controls are on keys that no keypad layout uses, e.g. Space pauses since P is a keypad key
+    fn handle_event(&mut self, event: event::Event) -> Option<Input> {
+        match event {
             event::Event::Quit { .. }
             | event::Event::KeyDown {
                 scancode: Some(Scancode::Escape),
@@ -32,14 +326,79 @@ impl InputDriver {
                 std::process::exit(1);
             }
 
-            event::Event::KeyDown { .. } => {
-                let last_key: Scancode = last_event.as_user_event_type().unwrap();
+            event::Event::KeyDown {
+                scancode: Some(Scancode::Space),
+                ..
+            } => Some(Input::Pause),
 
+            event::Event::KeyDown {
+                scancode: Some(Scancode::F2),
+                ..
+            } => Some(Input::ToggleInvert),
+
+            event::Event::KeyDown {
+                scancode: Some(Scancode::F3),
+                ..
+            } => Some(Input::ToggleDisplayWait),
+
+            event::Event::KeyDown {
+                scancode: Some(Scancode::F4),
+                ..
+            } => Some(Input::StepOut),
+
+            event::Event::KeyDown {
+                scancode: Some(Scancode::F5),
+                ..
+            } => Some(Input::SaveState),
+
+            event::Event::KeyDown {
+                scancode: Some(key),
+                ..
+            } => {
                 // Filter to only keys we care about
-                return convert_std_to_chip8_code(last_key);
+                let keycode = convert_std_to_chip8_code(&self.layout, key)?;
+
+                if self.keypad.press(keycode) {
+                    Some(Input::Key(keycode))
+                } else {
+                    None
+                }
+            }
+
+            event::Event::KeyUp {
+                scancode: Some(key),
+                ..
+            } => {
+                if let Some(keycode) = convert_std_to_chip8_code(&self.layout, key) {
+                    self.keypad.release(keycode);
+                }
+                None
             }
 
-            _ => return None,
+            event::Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => Some(Input::Click { x, y }),
+
+            event::Event::Window {
+                win_event: event::WindowEvent::FocusLost,
+                ..
+            } => {
+                self.focused = false;
+                None
+            }
+
+            event::Event::Window {
+                win_event: event::WindowEvent::FocusGained,
+                ..
+            } => {
+                self.focused = true;
+                None
+            }
+
+            _ => None,
         }
     }
 }
@@ -47,31 +406,254 @@ impl InputDriver {
 /*
     Attempts to map the keyboard keycode to the corresponding CHIP-8 Keycode.
 */
-fn convert_std_to_chip8_code(code: Scancode) -> Option<u8> {
-    return match code {
-        // First row 123C
-        Scancode::Num1 => Some(0x1),
-        Scancode::Num2 => Some(0x2),
-        Scancode::Num3 => Some(0x3),
-        Scancode::Num4 => Some(0xC),
-
-        // Second row 456D
-        Scancode::Q => Some(0x4),
-        Scancode::W => Some(0x5),
-        Scancode::E => Some(0x6),
-        Scancode::R => Some(0xD),
-
-        // Third row 789E
-        Scancode::A => Some(0x7),
-        Scancode::S => Some(0x8),
-        Scancode::D => Some(0x9),
-        Scancode::F => Some(0xE),
-
-        // Fourth row A0BF
-        Scancode::Z => Some(0xA),
-        Scancode::X => Some(0x0),
-        Scancode::C => Some(0xB),
-        Scancode::V => Some(0xF),
-        _ => None,
-    };
+fn convert_std_to_chip8_code(layout: &KeyLayout, code: Scancode) -> Option<u8> {
+    for (row, keys) in layout.iter().enumerate() {
+        if let Some(col) = keys.iter().position(|&key| key == code) {
+            return Some(CHIP8_KEYPAD[row][col]);
+        }
+    }
+
+    None
+}
+
+// Row and column of a CHIP-8 key on the keypad
+fn keypad_position(keycode: u8) -> (usize, usize) {
+    for (row, keys) in CHIP8_KEYPAD.iter().enumerate() {
+        if let Some(col) = keys.iter().position(|&key| key == keycode) {
+            return (row, col);
+        }
+    }
+
+    unreachable!("Every keycode from 0 to F is on the keypad")
+}
+
+fn format_keymap(layout: &KeyLayout) -> String {
+    let mut text = String::from("# CHIP-8 key = keyboard key\n");
+
+    for keycode in 0..16 {
+        let (row, col) = keypad_position(keycode);
+        text.push_str(&format!("{:X} = {}\n", keycode, layout[row][col].name()));
+    }
+
+    text
+}
+
+fn parse_keymap(text: &str) -> Result<KeyLayout, String> {
+    let mut layout = [[None; 4]; 4];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keycode, name) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY = SCANCODE", number + 1))?;
+        let (keycode, name) = (keycode.trim(), name.trim());
+
+        let keycode = match u8::from_str_radix(keycode, 16) {
+            Ok(keycode) if keycode < 16 => keycode,
+            _ => {
+                return Err(format!(
+                    "line {}: {} is not a key from 0 to F",
+                    number + 1,
+                    keycode
+                ))
+            }
+        };
+        let scancode = Scancode::from_name(name)
+            .ok_or_else(|| format!("line {}: unknown key name {}", number + 1, name))?;
+
+        if CONTROL_KEYS.contains(&scancode) {
+            return Err(format!(
+                "line {}: {} is an emulator control and can't be a keypad key",
+                number + 1,
+                name
+            ));
+        }
+
+        if let Some(other) = (0..16).find(|&other| {
+            let (row, col) = keypad_position(other);
+            layout[row][col] == Some(scancode)
+        }) {
+            return Err(format!(
+                "line {}: {} is already mapped to key {:X}",
+                number + 1,
+                name,
+                other
+            ));
+        }
+
+        let (row, col) = keypad_position(keycode);
+        if layout[row][col].replace(scancode).is_some() {
+            return Err(format!(
+                "line {}: key {:X} is mapped twice",
+                number + 1,
+                keycode
+            ));
+        }
+    }
+
+    let mut keys = STANDARD_LAYOUT;
+    for keycode in 0..16 {
+        let (row, col) = keypad_position(keycode);
+        keys[row][col] =
+            layout[row][col].ok_or_else(|| format!("key {:X} is not mapped", keycode))?;
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymaps_round_trip_for_every_layout() {
+        for layout in [Layout::Standard, Layout::Numpad, Layout::LeftHand] {
+            let keys = layout.keys();
+            assert_eq!(parse_keymap(&format_keymap(&keys)), Ok(keys));
+        }
+    }
+
+    #[test]
+    fn keys_are_found_at_their_keypad_position() {
+        let keys = parse_keymap(&format_keymap(&STANDARD_LAYOUT)).unwrap();
+
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::Num1), Some(0x1));
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::Num4), Some(0xC));
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::X), Some(0x0));
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::V), Some(0xF));
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::P), None);
+    }
+
+    // The standard keymap with one line replaced, counting from 1 like the errors do
+    fn keymap_with_line(number: usize, line: &str) -> String {
+        let mut lines: Vec<String> = format_keymap(&STANDARD_LAYOUT)
+            .lines()
+            .map(String::from)
+            .collect();
+        lines[number - 1] = line.to_string();
+
+        lines.join("\n")
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        // Line 1 is the comment, line 2 maps key 0 and so on
+        let cases = [
+            (3, "1 Q", "line 3: expected KEY = SCANCODE"),
+            (3, "G = Q", "line 3: G is not a key from 0 to F"),
+            (3, "1 = NoSuchKey", "line 3: unknown key name NoSuchKey"),
+            (3, "0 = P", "line 3: key 0 is mapped twice"),
+            (4, "2 = X", "line 4: X is already mapped to key 0"),
+            (
+                5,
+                "3 = Space",
+                "line 5: Space is an emulator control and can't be a keypad key",
+            ),
+            (
+                5,
+                "3 = Escape",
+                "line 5: Escape is an emulator control and can't be a keypad key",
+            ),
+            (
+                5,
+                "3 = F5",
+                "line 5: F5 is an emulator control and can't be a keypad key",
+            ),
+            (5, "# 3 is left out", "key 3 is not mapped"),
+        ];
+
+        for (number, line, error) in cases {
+            assert_eq!(
+                parse_keymap(&keymap_with_line(number, line)),
+                Err(error.to_string()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn function_keys_without_controls_can_be_mapped() {
+        let keys = parse_keymap(&keymap_with_line(2, "0 = F6")).unwrap();
+
+        assert_eq!(convert_std_to_chip8_code(&keys, Scancode::F6), Some(0x0));
+    }
+
+    #[test]
+    fn keymap_summary_lists_the_keypad_row_by_row() {
+        assert_eq!(
+            Keymap::from_layout(Layout::Standard).to_string(),
+            "1=1, 2=2, 3=3, C=4 | 4=Q, 5=W, 6=E, D=R | 7=A, 8=S, 9=D, E=F | A=Z, 0=X, B=C, F=V"
+        );
+    }
+
+    #[test]
+    fn keymap_files_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("chip-eight-keymap-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let keymap = Keymap::from_layout(Layout::LeftHand);
+        keymap.export(path).unwrap();
+        let imported = Keymap::import(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(imported, Ok(keymap));
+    }
+
+    #[test]
+    fn a_latched_key_is_taken_once() {
+        let mut keypad = Keypad::default();
+        assert!(keypad.press(0x5));
+
+        assert_eq!(keypad.latched, Some(0x5));
+        assert_eq!(keypad.latched.take(), Some(0x5));
+        assert_eq!(keypad.latched.take(), None);
+        assert!(keypad.is_pressed(0x5));
+    }
+
+    #[test]
+    fn holding_a_key_doesnt_latch_it_again() {
+        let mut keypad = Keypad::default();
+        keypad.press(0x5);
+        keypad.latched.take();
+
+        assert!(!keypad.press(0x5));
+        assert_eq!(keypad.latched, None);
+
+        keypad.release(0x5);
+        assert!(keypad.press(0x5));
+        assert_eq!(keypad.latched, Some(0x5));
+    }
+
+    #[test]
+    fn the_latch_holds_the_last_new_press() {
+        let mut keypad = Keypad::default();
+        keypad.press(0x1);
+        keypad.press(0x2);
+
+        assert_eq!(keypad.latched.take(), Some(0x2));
+        assert!(keypad.is_pressed(0x1) && keypad.is_pressed(0x2));
+    }
+
+    #[test]
+    fn presses_beyond_the_limit_are_ignored() {
+        let mut keypad = Keypad {
+            max_simultaneous_keys: Some(1),
+            ..Keypad::default()
+        };
+        keypad.press(0x1);
+        keypad.latched.take();
+
+        assert!(!keypad.press(0x2));
+        assert!(!keypad.is_pressed(0x2));
+        assert_eq!(keypad.latched, None);
+
+        keypad.release(0x1);
+        assert!(keypad.press(0x2));
+    }
 }
@@ -1,7 +1,13 @@
+mod audio;
 mod display;
 mod input;
+mod null;
 mod rom_reader;
+mod screenshot;
 
-pub use self::display::DisplayDriver;
-pub use self::input::InputDriver;
-pub use self::rom_reader::Rom;
+pub use self::audio::{AudioBackend, AudioDriver};
+pub use self::display::{DisplayBackend, DisplayDriver};
+pub use self::input::{Input, InputBackend, InputDriver, Keymap, Layout};
+pub use self::null::{NullDisplay, NullInput};
+pub use self::rom_reader::{Rom, RomWatcher};
+pub use self::screenshot::FrameDump;
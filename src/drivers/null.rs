@@ -0,0 +1,20 @@
+use super::{DisplayBackend, Input, InputBackend};
+
+const CHIP8_HEIGHT: usize = 32;
+const CHIP8_WIDTH: usize = 64;
+
+// Drivers that do nothing, for running the processor headless in tests, examples and tools
+
+pub struct NullDisplay;
+
+impl DisplayBackend for NullDisplay {
+    fn draw(&mut self, _pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]) {}
+}
+
+pub struct NullInput;
+
+impl InputBackend for NullInput {
+    fn last_input(&mut self) -> Option<Input> {
+        None
+    }
+}
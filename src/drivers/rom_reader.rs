@@ -1,31 +1,296 @@
-use std::{fs, io::Read};
+use std::{
+    error, fmt, fs, io,
+    time::{Duration, Instant, SystemTime},
+};
 
 // Program cannot be larger than 4096 - 512 bytes (first 512 bytes are reserved for the font)
 const CHIP8_MAX_ROM_SIZE: usize = 3584;
+const CHIP8_PROGRAM_MEMORY_START: usize = 0x200;
+
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 pub struct Rom {
     pub data: [u8; CHIP8_MAX_ROM_SIZE],
+    size: usize, // Bytes of data that came from the program, the rest is padding
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    TooLarge { size: usize, max: usize },
+    Empty { path: String },
+    Io { path: String, source: io::Error },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooLarge { size, max } => {
+                write!(f, "ROM is {} bytes; max is {}", size, max)
+            }
+            RomError::Empty { path } => write!(f, "ROM {} is empty", path),
+            RomError::Io { path, source } => write!(f, "Could not read ROM {}: {}", path, source),
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RomError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 impl Rom {
-    // New reads ROM file into bytes and
-    pub fn new(filename: &str) -> Self {
-        let mut rom_file = fs::File::open(filename).expect("Could not open file");
+    // New reads ROM file into bytes, failing if it can't be read or won't fit in program memory
+    pub fn new(filename: &str) -> Result<Self, RomError> {
+        let bytes = fs::read(filename).map_err(|source| RomError::Io {
+            path: String::from(filename),
+            source,
+        })?;
+
+        if bytes.is_empty() {
+            return Err(RomError::Empty {
+                path: String::from(filename),
+            });
+        }
+
+        let rom = Rom::from_bytes(&bytes)?;
+
+        println!("Read total of {} bytes from ROM", bytes.len());
+
+        Ok(rom)
+    }
+
+    // From bytes builds a ROM from a program that's already in memory, e.g. one embedded in the binary
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RomError> {
+        if bytes.len() > CHIP8_MAX_ROM_SIZE {
+            return Err(RomError::TooLarge {
+                size: bytes.len(),
+                max: CHIP8_MAX_ROM_SIZE,
+            });
+        }
 
         let mut buffer = [0u8; CHIP8_MAX_ROM_SIZE];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(Rom {
+            data: buffer,
+            size: bytes.len(),
+        })
+    }
+
+    // The bytes that came from the program, without the padding to the size of program memory
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.size]
+    }
+
+    /*
+        Swaps the bytes of every opcode, for dumps made by tools that store them little endian. The last byte of a
+        ROM with an odd size has nothing to swap with, so it's left where it is.
+    */
+    pub fn byteswap(&mut self) {
+        for pair in self.data[..self.size].chunks_exact_mut(2) {
+            pair.swap(0, 1);
+        }
+    }
+
+    /*
+        Finds runs of at least min_len printable ASCII bytes, like the Unix strings tool, and returns them with the
+        address they are loaded at. This is only a heuristic: CHIP-8 programs draw their text with sprites, so real
+        titles and credits rarely show up, while opcodes and sprite data regularly happen to look like short strings.
+    */
+    pub fn strings(&self, min_len: usize) -> Vec<(usize, String)> {
+        let mut strings = Vec::new();
+        let mut start = 0;
+
+        for (i, &byte) in self.bytes().iter().chain([0].iter()).enumerate() {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                continue;
+            }
+
+            if i - start >= min_len {
+                let text = String::from_utf8_lossy(&self.data[start..i]).into_owned();
+                strings.push((CHIP8_PROGRAM_MEMORY_START + start, text));
+            }
+
+            start = i + 1;
+        }
+
+        strings
+    }
+}
+
+/*
+    Polls a ROM file for changes so it can be reloaded while it's being worked on. The file counts as changed when
+    its modification time or size differs from the last version loaded, and it's only read once it stayed the same
+    for a whole poll interval, so an assembler that is still writing it isn't picked up halfway.
+*/
+pub struct RomWatcher {
+    path: String,
+    last_poll: Instant,
+    loaded: Option<(SystemTime, u64)>, // Modification time and size of the version that was last read
+    pending: Option<(SystemTime, u64)>, // Those of a change that hasn't settled yet
+    byteswap: bool,                    // Swap the bytes of reloaded ROMs, like the one first loaded
+}
 
-        // Attempt to fill the buffer
-        let bytes_read = match rom_file.read(&mut buffer) {
-            Ok(num_bytes) => num_bytes,
-            Err(_) => 0,
+impl RomWatcher {
+    pub fn new(path: &str) -> Self {
+        RomWatcher {
+            path: String::from(path),
+            last_poll: Instant::now(),
+            loaded: file_stamp(path),
+            pending: None,
+            byteswap: false,
+        }
+    }
+
+    pub fn set_byteswap(&mut self, byteswap: bool) {
+        self.byteswap = byteswap;
+    }
+
+    // Returns the result of reading the file again once a change settled, None the rest of the time
+    pub fn poll(&mut self) -> Option<Result<Rom, RomError>> {
+        if self.last_poll.elapsed() < WATCH_POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let stamp = file_stamp(&self.path);
+
+        if stamp == self.loaded {
+            self.pending = None;
+            return None;
+        }
+
+        if stamp != self.pending {
+            self.pending = stamp;
+            return None;
+        }
+
+        self.loaded = stamp;
+        self.pending = None;
+
+        let mut rom = Rom::new(&self.path);
+        if let (true, Ok(rom)) = (self.byteswap, &mut rom) {
+            rom.byteswap();
+        }
+
+        Some(rom)
+    }
+}
+
+fn file_stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    // A path in the temp directory that no other test process uses
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("chip-eight-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn too_large_rom_reports_its_size_and_the_maximum() {
+        let err = Rom::from_bytes(&[0; CHIP8_MAX_ROM_SIZE + 1]).err().unwrap();
+
+        assert!(matches!(
+            err,
+            RomError::TooLarge {
+                size: 3585,
+                max: 3584
+            }
+        ));
+        assert_eq!(err.to_string(), "ROM is 3585 bytes; max is 3584");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn empty_rom_file_is_rejected() {
+        let path = temp_path("empty.ch8");
+        fs::write(&path, []).unwrap();
+
+        let err = Rom::new(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(&err, RomError::Empty { path: p } if *p == path));
+        assert_eq!(err.to_string(), format!("ROM {} is empty", path));
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn unreadable_rom_file_reports_the_path_and_the_cause() {
+        let err = Rom::new("no/such/rom.ch8").err().unwrap();
+
+        let RomError::Io { path, source } = &err else {
+            panic!("Expected an I/O error, got {}", err);
         };
+        assert_eq!(path, "no/such/rom.ch8");
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            err.to_string(),
+            format!("Could not read ROM no/such/rom.ch8: {}", source)
+        );
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn rom_that_fits_is_padded_to_the_program_memory() {
+        let rom = Rom::from_bytes(&[0x12, 0x00]).unwrap();
+
+        assert_eq!(rom.data[..2], [0x12, 0x00]);
+        assert!(rom.data[2..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn bytes_leave_out_the_padding() {
+        let rom = Rom::from_bytes(&[0x12, 0x00, 0x00]).unwrap();
+
+        assert_eq!(rom.bytes(), [0x12, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn byteswap_leaves_the_last_byte_of_an_odd_size_rom() {
+        let mut rom = Rom::from_bytes(&[0xE0, 0x00, 0x0C, 0x60, 0x07]).unwrap();
+        rom.byteswap();
+
+        assert_eq!(rom.size, 5);
+        assert_eq!(rom.data[..6], [0x00, 0xE0, 0x60, 0x0C, 0x07, 0x00]);
+    }
+
+    #[test]
+    fn byteswapped_dump_runs_like_the_original() {
+        use crate::drivers::{NullDisplay, NullInput};
+        use crate::processor::Processor;
 
-        println!("Read total of {} bytes from ROM", bytes_read);
+        let original = crate::roms::hello_rom_bytes();
+        let dump: Vec<u8> = original
+            .chunks(2)
+            .flat_map(|pair| [pair[1], pair[0]])
+            .collect();
+        let mut swapped = Rom::from_bytes(&dump).unwrap();
+        swapped.byteswap();
 
-        if bytes_read == 0 {
-            panic!("Failed to read ROM")
+        let mut frames = Vec::new();
+        for program in [&original[..], swapped.bytes()] {
+            let mut processor = Processor::new(NullDisplay, NullInput);
+            processor.load_program(program);
+            for _ in 0..20 {
+                processor.step(None).unwrap();
+            }
+            frames.push(*processor.framebuffer());
         }
 
-        Rom { data: buffer }
+        assert_eq!(*swapped.bytes(), original[..]);
+        assert_eq!(frames[0], frames[1]);
+        assert!(frames[0].iter().flatten().any(|&pixel| pixel == 1));
     }
 }
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CHIP8_HEIGHT: usize = 32;
+const CHIP8_WIDTH: usize = 64;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/*
+    Saves the framebuffer as a PNG at its native 64x32 resolution, one image pixel per CHIP-8 pixel, using the
+    colors of the display. The image data is small enough to fit in a single uncompressed deflate block, which
+    keeps the encoder dependency free.
+*/
+pub fn save_screenshot(
+    path: &Path,
+    pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    color: impl Fn(u8) -> (u8, u8, u8),
+) -> io::Result<()> {
+    // Each scanline starts with its filter type (0, no filter) followed by RGB triples
+    let mut image_data = Vec::with_capacity(CHIP8_HEIGHT * (1 + CHIP8_WIDTH * 3));
+    for row in pixels.iter() {
+        image_data.push(0);
+        for &value in row.iter() {
+            let (r, g, b) = color(value);
+            image_data.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(CHIP8_WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(CHIP8_HEIGHT as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit depth, truecolor, no interlacing
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&image_data));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    fs::write(path, png)
+}
+
+// Writes every Nth rendered frame to a directory as numbered PNGs, stopping after a maximum number of images
+pub struct FrameDump {
+    dir: PathBuf,
+    every: usize,
+    max_frames: usize,
+    frame_index: usize,
+    saved: usize,
+}
+
+impl FrameDump {
+    pub fn new(dir: &str, every: usize, max_frames: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        Ok(FrameDump {
+            dir: PathBuf::from(dir),
+            every,
+            max_frames,
+            frame_index: 0,
+            saved: 0,
+        })
+    }
+
+    // Returns the path of the image when this frame was saved, None when it was skipped
+    pub fn capture(
+        &mut self,
+        pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+        color: impl Fn(u8) -> (u8, u8, u8),
+    ) -> io::Result<Option<PathBuf>> {
+        let frame_index = self.frame_index;
+        self.frame_index += 1;
+
+        if self.saved >= self.max_frames || !frame_index.is_multiple_of(self.every) {
+            return Ok(None);
+        }
+
+        let path = self.dir.join(format!("frame_{:06}.png", frame_index));
+        save_screenshot(&path, pixels, color)?;
+
+        self.saved += 1;
+        if self.saved == self.max_frames {
+            println!("Dumped {} frames, no more will be saved", self.saved);
+        }
+
+        Ok(Some(path))
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wraps data in a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        stream.push(is_final as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
@@ -0,0 +1,83 @@
+use std::{error, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum CpuError {
+    IndexOutOfRange {
+        value: usize,
+    },
+    UnknownOpcode {
+        pc: usize,
+        opcode: u16,
+    },
+    StackOverflow {
+        pc: usize,
+    },
+    StackUnderflow {
+        pc: usize,
+    },
+    MemoryOutOfRange {
+        pc: usize,
+        address: usize,
+    },
+    ReservedMemoryWrite {
+        pc: usize,
+        address: usize,
+    },
+    Watchpoint {
+        pc: usize,
+        address: usize,
+        value: u8,
+    },
+    EmptySprite {
+        pc: usize,
+    },
+    EntryPointOutOfRange {
+        address: usize,
+    },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::IndexOutOfRange { value } => {
+                write!(f, "Index 0x{:X} is outside of memory", value)
+            }
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "0x{:04X} at 0x{:03X} is not supported", opcode, pc)
+            }
+            CpuError::StackOverflow { pc } => write!(f, "Stack overflow at 0x{:03X}", pc),
+            CpuError::StackUnderflow { pc } => {
+                write!(f, "Return with an empty stack at 0x{:03X}", pc)
+            }
+            CpuError::MemoryOutOfRange { pc, address } => {
+                write!(
+                    f,
+                    "Access to 0x{:X} outside of memory at 0x{:03X}",
+                    address, pc
+                )
+            }
+            CpuError::ReservedMemoryWrite { pc, address } => write!(
+                f,
+                "Write to 0x{:03X}, used by the VIP interpreter, at 0x{:03X}",
+                address, pc
+            ),
+            CpuError::Watchpoint { pc, address, value } => write!(
+                f,
+                "Watchpoint: 0x{:03X} wrote 0x{:02X} to 0x{:03X}",
+                pc, value, address
+            ),
+            CpuError::EmptySprite { pc } => write!(
+                f,
+                "DXY0 at 0x{:03X} draws a sprite 0 rows high, which CHIP-8 doesn't define",
+                pc
+            ),
+            CpuError::EntryPointOutOfRange { address } => write!(
+                f,
+                "Entry point 0x{:X} leaves no room for an instruction in memory",
+                address
+            ),
+        }
+    }
+}
+
+impl error::Error for CpuError {}
@@ -0,0 +1,188 @@
+use std::io::Write;
+
+/*
+    High-level events for external tools, written as one JSON object per line. Every object has an "event" field
+    naming it, the other fields depend on the event. Addresses, opcodes and values are plain JSON numbers.
+
+        {"event":"rom_loaded","size":478}                       A program of size bytes was loaded
+        {"event":"reset"}                                       The machine returned to its power-on state
+        {"event":"paused"} and {"event":"resumed"}              Execution was paused or resumed
+        {"event":"unknown_opcode","pc":514,"opcode":61695}      An unsupported instruction was skipped
+        {"event":"watchpoint","pc":516,"address":769,"value":2} An instruction wrote to a watched address
+        {"event":"fault","pc":530,"message":"Stack overflow"}   The processor recovered from a fault
+        {"event":"halted","pc":514,"reason":"..."}              Execution stopped, e.g. at an unknown opcode
+        {"event":"screenshot_saved","path":"frames/..."}        A frame was saved as an image
+
+    Tools should ignore events and fields they don't know, so new ones can be added later. On stdout the events are
+    interleaved with the emulator's other messages, but only event lines start with {.
+*/
+pub enum Event<'a> {
+    RomLoaded {
+        size: usize,
+    },
+    Reset,
+    Paused,
+    Resumed,
+    UnknownOpcode {
+        pc: usize,
+        opcode: u16,
+    },
+    Watchpoint {
+        pc: usize,
+        address: usize,
+        value: u8,
+    },
+    Fault {
+        pc: usize,
+        message: &'a str,
+    },
+    Halted {
+        pc: usize,
+        reason: &'a str,
+    },
+    ScreenshotSaved {
+        path: &'a str,
+    },
+}
+
+impl Event<'_> {
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::RomLoaded { size } => format!(r#"{{"event":"rom_loaded","size":{}}}"#, size),
+            Event::Reset => String::from(r#"{"event":"reset"}"#),
+            Event::Paused => String::from(r#"{"event":"paused"}"#),
+            Event::Resumed => String::from(r#"{"event":"resumed"}"#),
+            Event::UnknownOpcode { pc, opcode } => format!(
+                r#"{{"event":"unknown_opcode","pc":{},"opcode":{}}}"#,
+                pc, opcode
+            ),
+            Event::Watchpoint { pc, address, value } => format!(
+                r#"{{"event":"watchpoint","pc":{},"address":{},"value":{}}}"#,
+                pc, address, value
+            ),
+            Event::Fault { pc, message } => format!(
+                r#"{{"event":"fault","pc":{},"message":{}}}"#,
+                pc,
+                json_string(message)
+            ),
+            Event::Halted { pc, reason } => format!(
+                r#"{{"event":"halted","pc":{},"reason":{}}}"#,
+                pc,
+                json_string(reason)
+            ),
+            Event::ScreenshotSaved { path } => format!(
+                r#"{{"event":"screenshot_saved","path":{}}}"#,
+                json_string(path)
+            ),
+        }
+    }
+}
+
+pub struct EventLog {
+    output: Box<dyn Write>,
+}
+
+impl EventLog {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        EventLog { output }
+    }
+
+    pub fn emit(&mut self, event: Event) -> std::io::Result<()> {
+        writeln!(self.output, "{}", event.to_json())?;
+        self.output.flush()
+    }
+}
+
+// Quotes and escapes text as a JSON string
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_follow_the_schema() {
+        let events = [
+            (
+                Event::RomLoaded { size: 478 },
+                r#"{"event":"rom_loaded","size":478}"#,
+            ),
+            (Event::Reset, r#"{"event":"reset"}"#),
+            (Event::Paused, r#"{"event":"paused"}"#),
+            (Event::Resumed, r#"{"event":"resumed"}"#),
+            (
+                Event::UnknownOpcode {
+                    pc: 514,
+                    opcode: 0xF0FF,
+                },
+                r#"{"event":"unknown_opcode","pc":514,"opcode":61695}"#,
+            ),
+            (
+                Event::Watchpoint {
+                    pc: 516,
+                    address: 769,
+                    value: 2,
+                },
+                r#"{"event":"watchpoint","pc":516,"address":769,"value":2}"#,
+            ),
+            (
+                Event::Fault {
+                    pc: 530,
+                    message: "Stack overflow",
+                },
+                r#"{"event":"fault","pc":530,"message":"Stack overflow"}"#,
+            ),
+            (
+                Event::Halted {
+                    pc: 514,
+                    reason: "Unknown opcode",
+                },
+                r#"{"event":"halted","pc":514,"reason":"Unknown opcode"}"#,
+            ),
+            (
+                Event::ScreenshotSaved {
+                    path: "frames/frame_000000.png",
+                },
+                r#"{"event":"screenshot_saved","path":"frames/frame_000000.png"}"#,
+            ),
+        ];
+
+        for (event, json) in events {
+            assert_eq!(event.to_json(), json);
+        }
+    }
+
+    #[test]
+    fn strings_escape_quotes_and_backslashes() {
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\frames"), r#""C:\\frames""#);
+    }
+
+    #[test]
+    fn strings_escape_control_characters() {
+        assert_eq!(json_string("a\nb"), r#""a\nb""#);
+        assert_eq!(json_string("\t\r\u{1}"), r#""\u0009\u000d\u0001""#);
+        assert_eq!(json_string("\u{1F}\u{20}"), "\"\\u001f \"");
+    }
+
+    #[test]
+    fn strings_keep_other_characters() {
+        assert_eq!(json_string(""), r#""""#);
+        assert_eq!(json_string("delay 0x3C é"), r#""delay 0x3C é""#);
+    }
+}
@@ -1,3 +1,8 @@
+/*
+    The small font, a 5 byte glyph for each hex digit 0-F, loaded at address 0 so FX29 finds digit N at N * 5. The
+    glyphs take up 0x000-0x04F. There's no large 10 byte font for SUPER-CHIP's FX30 yet; when it's added it must
+    start at or after 0x050 so the two don't overlap.
+*/
 pub const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
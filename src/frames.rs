@@ -0,0 +1,74 @@
+use crate::{CHIP8_DISPLAY_HEIGHT, CHIP8_DISPLAY_WIDTH};
+
+/*
+    Helpers for checking what a program drew, e.g. in examples and tools that run the processor headless. Frames
+    are drawn as ASCII art with a '#' for every set pixel and a '.' for every unset one, a line per row.
+*/
+
+pub type Frame = [[u8; CHIP8_DISPLAY_WIDTH]; CHIP8_DISPLAY_HEIGHT];
+
+pub fn to_ascii(frame: &Frame) -> String {
+    let lines: Vec<String> = frame.iter().map(|row| ascii_row(row)).collect();
+
+    lines.join("\n")
+}
+
+/*
+    Builds a frame with the ASCII art drawn with its top left corner at (x, y) and every other pixel unset. Any
+    character other than '#' is an unset pixel, and art running off the display is cut off.
+*/
+pub fn from_ascii(lines: &[&str], (x, y): (usize, usize)) -> Frame {
+    let mut frame = [[0; CHIP8_DISPLAY_WIDTH]; CHIP8_DISPLAY_HEIGHT];
+
+    for (row, line) in frame.iter_mut().skip(y).zip(lines) {
+        for (pixel, art) in row.iter_mut().skip(x).zip(line.bytes()) {
+            *pixel = (art == b'#') as u8;
+        }
+    }
+
+    frame
+}
+
+/*
+    Panics unless both frames are the same. The message has the number of differing pixels and both frames side by
+    side, with the rows that differ marked, so a failed check shows what went wrong without a debugger.
+*/
+#[track_caller]
+pub fn assert_frames_eq(actual: &Frame, expected: &Frame) {
+    let differences = actual
+        .iter()
+        .flatten()
+        .zip(expected.iter().flatten())
+        .filter(|(a, e)| a != e)
+        .count();
+
+    if differences == 0 {
+        return;
+    }
+
+    let mut message = format!(
+        "Frames differ in {} pixels\n{:<width$}   expected\n",
+        differences,
+        "actual",
+        width = CHIP8_DISPLAY_WIDTH
+    );
+
+    for (actual_row, expected_row) in actual.iter().zip(expected) {
+        let marker = if actual_row == expected_row { " " } else { "<" };
+
+        message.push_str(&format!(
+            "{} {} {}\n",
+            ascii_row(actual_row),
+            marker,
+            ascii_row(expected_row)
+        ));
+    }
+
+    panic!("{}", message);
+}
+
+fn ascii_row(row: &[u8]) -> String {
+    row.iter()
+        .map(|&pixel| if pixel == 0 { '.' } else { '#' })
+        .collect()
+}
@@ -0,0 +1,17 @@
+mod debugger;
+pub mod disasm;
+pub mod drivers;
+pub mod error;
+mod events;
+mod font;
+pub mod frames;
+mod lint;
+pub mod processor;
+pub mod quirks;
+pub mod roms;
+mod timing;
+pub mod trace;
+
+pub const CHIP8_DISPLAY_WIDTH: usize = 64; // 64px wide
+pub const CHIP8_DISPLAY_HEIGHT: usize = 32; // 32px tall
+pub const CHIP8_MEMORY: usize = 4096; // 4 KB RAM asvailable
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+/*
+    Heuristic lint for ROM authors that warns when VF is read as an operand while it may hold a stale flag.
+
+    Several instructions write a flag to VF as a side effect (8XY4, 8XY5, 8XY6, 8XY7, 8XYE, DXYN and FX1E). A ROM
+    normally reads such a flag once, straight after the instruction that produced it. The lint treats VF as fresh
+    after a flag-producing instruction or an explicit write to VF (e.g. 6FNN), and as consumed once an instruction
+    reads it. Reading VF while it is not fresh produces a warning, once per address.
+
+    This is only a heuristic. ROMs that use VF as an ordinary register, or that deliberately read a flag more than
+    once, will produce false positives, which is why the lint is off by default.
+*/
+pub struct VfLint {
+    fresh: bool,
+    warned: HashSet<usize>,
+}
+
+impl VfLint {
+    pub fn new() -> Self {
+        VfLint {
+            fresh: false,
+            warned: HashSet::new(),
+        }
+    }
+
+    // Called with each instruction before it is executed
+    pub fn check(&mut self, pc: usize, instruction: u16) {
+        let reads_vf = reads_vf(instruction);
+
+        if reads_vf && !self.fresh && self.warned.insert(pc) {
+            println!(
+                "VF lint: 0x{:03X} ({:04X}) reads VF, but no flag-producing instruction or write to VF has run since it was last read",
+                pc, instruction
+            );
+        }
+
+        if sets_flag(instruction) || writes_vf(instruction) {
+            self.fresh = true;
+        } else if reads_vf {
+            self.fresh = false;
+        }
+    }
+}
+
+fn nibbles(instruction: u16) -> (u16, u16, u16, u16) {
+    (
+        (instruction & 0xF000) >> 12,
+        (instruction & 0x0F00) >> 8,
+        (instruction & 0x00F0) >> 4,
+        instruction & 0x000F,
+    )
+}
+
+// Whether the instruction uses VF as one of its operands
+fn reads_vf(instruction: u16) -> bool {
+    let (op, x, y, n) = nibbles(instruction);
+
+    match (op, n) {
+        (0x3, _) | (0x4, _) | (0x7, _) => x == 0xF,
+        (0x5, 0x0) | (0x9, 0x0) | (0xD, _) => x == 0xF || y == 0xF,
+        (0x8, 0x0) => y == 0xF,
+        (0x8, _) => x == 0xF || y == 0xF,
+        (0xE, _) => x == 0xF,
+        (0xF, _) => {
+            x == 0xF
+                && matches!(
+                    instruction & 0x00FF,
+                    0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55
+                )
+        }
+        _ => false,
+    }
+}
+
+// Whether the instruction produces a flag in VF as a side effect
+fn sets_flag(instruction: u16) -> bool {
+    let (op, _, _, n) = nibbles(instruction);
+
+    match op {
+        0x8 => matches!(n, 0x4 | 0x5 | 0x6 | 0x7 | 0xE),
+        0xD => true,
+        0xF => instruction & 0x00FF == 0x1E,
+        _ => false,
+    }
+}
+
+// Whether the instruction stores a value in VF as its destination register
+fn writes_vf(instruction: u16) -> bool {
+    let (op, x, _, n) = nibbles(instruction);
+
+    x == 0xF
+        && match op {
+            0x6 | 0x7 | 0xC => true,
+            0x8 => n <= 0x3,
+            0xF => matches!(instruction & 0x00FF, 0x07 | 0x0A | 0x65),
+            _ => false,
+        }
+}
@@ -1,23 +1,263 @@
-mod drivers;
-mod font;
-mod processor;
+mod config;
 
-use drivers::{DisplayDriver, InputDriver, Rom};
-use processor::Processor;
+use std::{
+    env, fs,
+    io::{self, LineWriter},
+    time::Duration,
+};
 
-const CHIP8_DISPLAY_WIDTH: usize = 64; // 64px wide
-const CHIP8_DISPLAY_HEIGHT: usize = 32; // 32px tall
-const CHIP8_MEMORY: usize = 4096; // 4 KB RAM asvailable
+use chip_eight::drivers::{
+    AudioDriver, DisplayDriver, FrameDump, InputDriver, Keymap, NullDisplay, NullInput, Rom,
+    RomWatcher,
+};
+use chip_eight::processor::Processor;
+use chip_eight::roms;
+use chip_eight::trace;
+use config::Config;
 
 fn main() {
-    let rom = Rom::new("roms/test_opcode.ch8");
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(2);
+        }
+    };
+
+    let keymap = match &config.keymap_path {
+        Some(path) => match Keymap::import(path) {
+            Ok(keymap) => keymap,
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(2);
+            }
+        },
+        None => Keymap::from_layout(config.layout),
+    };
+
+    if let Some(path) = &config.export_keymap_path {
+        match keymap.export(path) {
+            Ok(()) => println!("Saved keymap to {}", path),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    if config.show_config {
+        println!("{:#?}", config);
+        println!("{:#?}", config.variant.quirks());
+        println!("Keymap: {}", keymap);
+        return;
+    }
+
+    /*
+        Fall back to the hello ROM so there's always something to run. It's assembled in code rather than embedding
+        one of the ROMs in roms/ with include_bytes!, since their origin and license are unknown.
+    */
+    let rom = match &config.rom_path {
+        Some(path) => Rom::new(path),
+        None => {
+            println!("No ROM given, running the built in hello ROM");
+            Rom::from_bytes(&roms::hello_rom_bytes())
+        }
+    };
+
+    let mut rom = match rom {
+        Ok(rom) => rom,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if config.byteswap {
+        rom.byteswap();
+    }
+
+    if config.strings {
+        for (address, text) in rom.strings(config.strings_min) {
+            println!("0x{:03X} {}", address, text);
+        }
+        return;
+    }
+
+    // Modes that only print a result run without a window, so they also work e.g. on CI machines without a display
+    if config.run_opcodes.is_some()
+        || config.compare_quirk.is_some()
+        || config.compare_trace_path.is_some()
+    {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        configure(&mut processor, &config);
+        run_headless(&mut processor, &config, &rom);
+        return;
+    }
+
     let sdl_context = sdl2::init().unwrap();
-    let disp = DisplayDriver::new(&sdl_context);
-    let inp = InputDriver::new(&sdl_context);
+    let mut disp = DisplayDriver::new(&sdl_context);
+    let mut inp = InputDriver::new(&sdl_context, keymap);
+
+    if let Some(max) = config.max_keys {
+        inp.set_max_simultaneous_keys(max);
+    }
+
+    disp.set_inverted(config.invert);
+
+    if let Some(frames) = config.fade_frames {
+        disp.set_fade_frames(frames);
+    }
+
+    if let Some(dir) = &config.dump_frames_dir {
+        let frame_dump = FrameDump::new(dir, config.dump_every, config.dump_max)
+            .expect("Could not create frame dump directory");
+        disp.set_frame_dump(frame_dump);
+    }
 
     let mut processor = Processor::new(disp, inp);
+    configure(&mut processor, &config);
+
+    // Not every machine has a sound card, so carry on silently without one
+    match AudioDriver::new(&sdl_context) {
+        Ok(audio) => processor.set_audio_driver(audio),
+        Err(err) => println!("No sound: {}", err),
+    }
+
+    if config.loop_rom {
+        processor.enable_loop(Duration::from_secs(config.loop_delay_secs));
+    }
+
+    if config.paint {
+        processor.enable_paint_mode();
+    }
+
+    if let Some(instructions) = config.pause_after {
+        processor.set_pause_after(instructions);
+    }
+
+    if config.debugger {
+        processor.enable_debug_console();
+    }
+
+    if config.timing_stats {
+        processor.enable_timing_stats();
+    }
+
+    if let Some(path) = &config.save_state_path {
+        processor.set_save_state_path(path);
+    }
+
+    if let Some(dir) = &config.snapshot_dir {
+        processor.set_snapshot_dir(dir);
+    }
 
-    processor.load_program(&rom.data);
+    if let (true, Some(path)) = (config.watch, &config.rom_path) {
+        let mut rom_watcher = RomWatcher::new(path);
+        rom_watcher.set_byteswap(config.byteswap);
+        processor.set_rom_watcher(rom_watcher);
+    }
+
+    processor.load_program(rom.bytes());
+    load_state(&mut processor, &config);
 
     processor.start();
 }
+
+// Applies the options that affect how the program runs, for both the window and the headless modes
+fn configure(processor: &mut Processor, config: &Config) {
+    processor.set_quirks(config.variant.quirks());
+    processor.set_timer_hz(config.timer_hz);
+    processor.set_refresh_hz(config.refresh_hz);
+    processor.set_draw_cost(config.draw_cost);
+    processor.set_max_frameskip(config.max_frameskip);
+    processor.set_present_each_draw(config.author);
+    processor.set_defer_clear(config.defer_clear);
+    processor.set_unknown_opcode_policy(config.on_unknown);
+    processor.set_empty_sprite_policy(config.on_dxy0);
+    processor.set_fault_overlay(config.fault_overlay);
+
+    if config.busy_slowdown {
+        processor.enable_busy_slowdown();
+    }
+
+    if config.lint_vf {
+        processor.enable_vf_lint();
+    }
+
+    if let Some(seed) = config.seed {
+        processor.set_seed(seed);
+    }
+
+    if let Some(path) = &config.trace_path {
+        let trace_file = fs::File::create(path).expect("Could not create trace file");
+        processor.set_trace_output(Box::new(LineWriter::new(trace_file)));
+    }
+
+    match config.events_path.as_deref() {
+        Some("-") => processor.set_event_output(Box::new(io::stdout())),
+        Some(path) => {
+            let events_file = fs::File::create(path).expect("Could not create events file");
+            processor.set_event_output(Box::new(events_file));
+        }
+        None => {}
+    }
+
+    if let Some(entry) = config.entry {
+        if let Err(err) = processor.set_entry_point(entry) {
+            println!("{}", err);
+            std::process::exit(2);
+        }
+    }
+}
+
+// A state replaces the whole machine, program included
+fn load_state(processor: &mut Processor, config: &Config) {
+    if let Some(path) = &config.load_state_path {
+        if let Err(err) = processor.load_state(path) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Runs the modes that print a result instead of opening a window: --run-opcodes, --compare-quirk and --compare-trace
+fn run_headless(processor: &mut Processor, config: &Config, rom: &Rom) {
+    if let Some(ops) = &config.run_opcodes {
+        processor.run_opcodes(ops);
+        processor.print_registers();
+        return;
+    }
+
+    if let Some(name) = &config.compare_quirk {
+        let quirks = config.variant.quirks();
+        let toggled = quirks.toggled(name).unwrap();
+        let seed = config.seed.unwrap_or_else(rand::random);
+
+        match processor.compare_quirks(rom.bytes(), (quirks, toggled), seed, config.cycles) {
+            Some(divergence) => println!("Quirk {} affects this ROM: {}", name, divergence),
+            None => println!("Quirk {} had no effect over {} cycles", name, config.cycles),
+        }
+
+        return;
+    }
+
+    processor.load_program(rom.bytes());
+    load_state(processor, config);
+
+    if let Some(path) = &config.compare_trace_path {
+        let text = fs::read_to_string(path).expect("Could not read reference trace");
+
+        let reference = match trace::parse_trace(&text) {
+            Ok(reference) => reference,
+            Err(message) => {
+                println!("{}", message);
+                std::process::exit(2);
+            }
+        };
+
+        match processor.compare_with_trace(&reference) {
+            Some(divergence) => {
+                println!("{}", divergence);
+                std::process::exit(1);
+            }
+            None => println!("Matched reference for all {} cycles", reference.len()),
+        }
+    }
+}
@@ -1,13 +1,165 @@
-use std::{thread, time};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io::Write,
+    ops::{Range, RangeInclusive},
+    path::Path,
+    thread, time,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    drivers::{DisplayDriver, InputDriver},
+    debugger::{self, Command, Console},
+    disasm::{self, Operand},
+    drivers::{AudioBackend, DisplayBackend, Input, InputBackend, RomWatcher},
+    error::CpuError,
+    events::{Event, EventLog},
     font::FONT_SET,
+    lint::VfLint,
+    quirks::Quirks,
+    timing::TimingStats,
+    trace::{Divergence, TraceEntry},
     CHIP8_MEMORY,
 };
 
+mod state;
+
+pub use self::state::StateError;
+
 const CHIP8_PROGRAM_MEMORY_START: usize = 0x200;
 const CHIP8_VF_INDEX: usize = 0x0F;
+const CHIP8_CLOCK_HZ: u32 = 200; // One instruction every 5 milliseconds
+const DEFAULT_TIMER_HZ: u32 = 60;
+const DEFAULT_DRAW_COST: u32 = 1; // Cycles DXYN takes, like every other instruction unless configured otherwise
+const DEFAULT_REFRESH_HZ: u32 = 60; // Rate changes to the display are presented at, unless presenting every draw
+const SLOWDOWN_DRAWS_PER_CYCLE: u32 = 16; // Draws in a frame that add one cycle to each instruction of the next
+const SLOWDOWN_MAX_DRAWS: u32 = 32; // Busier frames don't slow down further, at most 3 cycles per instruction
+const VIP_INTERPRETER_RAM: Range<usize> = 0xEA0..0xF00; // Where the VIP interpreter kept its stack and variables
+const VIP_DISPLAY_RAM: Range<usize> = 0xF00..0x1000; // Where the VIP kept the display, one bit per pixel
+const STEP_OUT_MAX_CYCLES: usize = 100_000;
+const RUN_OPCODES_MAX_CYCLES: usize = 100_000; // Keeps run_opcodes from hanging on a jump-to-self or FX0A
+const DEFAULT_MAX_FRAMESKIP: u32 = 2; // Frames in a row that may go unpresented while running behind real time
+const MAX_LAG: time::Duration = time::Duration::from_millis(100); // Further behind, catching up is given up on
+const HALT_ZERO_RUN: u32 = 32; // Consecutive 0000 opcodes after which the PC is taken to have run off the program
+
+// Machine state after an instruction, as compared between the runs of compare_quirks
+#[derive(PartialEq)]
+pub struct Snapshot {
+    entry: TraceEntry,
+    registers: [u8; 16],
+    index: usize,
+    sp: usize,
+    display_hash: u64,
+}
+
+impl Snapshot {
+    // Describes each part of the state that differs from the other snapshot
+    fn differences(&self, other: &Snapshot) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if self.entry != other.entry {
+            differences.push(format!("executed {} vs {}", self.entry, other.entry));
+        }
+        for (i, (a, b)) in self
+            .registers
+            .iter()
+            .zip(other.registers.iter())
+            .enumerate()
+        {
+            if a != b {
+                differences.push(format!("V{:X} {:02X} vs {:02X}", i, a, b));
+            }
+        }
+        if self.index != other.index {
+            differences.push(format!("I {:03X} vs {:03X}", self.index, other.index));
+        }
+        if self.sp != other.sp {
+            differences.push(format!("stack depth {} vs {}", self.sp, other.sp));
+        }
+        if self.display_hash != other.display_hash {
+            differences.push(String::from("display contents"));
+        }
+
+        differences
+    }
+}
+
+pub struct QuirkDivergence {
+    pub cycle: usize,
+    first: Snapshot,
+    second: Snapshot,
+}
+
+impl fmt::Display for QuirkDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "first divergence at cycle {} after {}: {}",
+            self.cycle,
+            self.first.entry,
+            self.first.differences(&self.second).join(", ")
+        )
+    }
+}
+
+// What set_index does with a value that doesn't address memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexPolicy {
+    Wrap,  // Keep the address modulo the memory size, like the 12 bit address bus (default)
+    Clamp, // Use the last memory address instead
+    Error, // Reject the value and leave I unchanged
+}
+
+// What step does when it fetches an instruction the emulator doesn't implement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownOpcodePolicy {
+    Skip,  // Log it and carry on with the next instruction (default)
+    Halt,  // Stop in front of it: step returns an error and the main loop pauses
+    Panic, // For tests that want unimplemented instructions to fail loudly
+}
+
+/*
+   What DXYN does with a height of 0, which base CHIP-8 leaves undefined. SUPER-CHIP draws a 16x16 sprite then, but
+   this emulator has no SUPER-CHIP mode (no Variant for it, no 128x64 display and no 16x16 sprites), so DXY0 goes
+   through this policy whatever the variant, and SUPER-CHIP ROMs relying on it draw nothing there.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmptySpritePolicy {
+    Ignore, // Draw nothing, only clearing VF like any other draw without a collision (default)
+    Warn,   // Like ignore, but report a fault the first time an instruction does it
+    Error,  // Like ignore, then halt: step returns an error and the main loop pauses
+}
+
+impl EmptySpritePolicy {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "ignore" => Ok(EmptySpritePolicy::Ignore),
+            "warn" => Ok(EmptySpritePolicy::Warn),
+            "error" => Ok(EmptySpritePolicy::Error),
+            _ => Err(format!(
+                "Unknown DXY0 policy {}, expected one of: ignore, warn, error",
+                name
+            )),
+        }
+    }
+}
+
+impl UnknownOpcodePolicy {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "skip" => Ok(UnknownOpcodePolicy::Skip),
+            "halt" => Ok(UnknownOpcodePolicy::Halt),
+            "panic" => Ok(UnknownOpcodePolicy::Panic),
+            _ => Err(format!(
+                "Unknown opcode policy {}, expected one of: skip, halt, panic",
+                name
+            )),
+        }
+    }
+}
+
 pub struct Processor {
     ram: [u8; CHIP8_MEMORY],
     display: [[u8; 64]; 32],
@@ -18,12 +170,63 @@ pub struct Processor {
     sp: usize,               // Point at next element in the stack
     sound_timer: u8,         // Used to produce beep if value >0
     delay_timer: u8,         // Decremented 60 times per second until it reaches 0
-    display_driver: DisplayDriver,
-    input_driver: InputDriver,
+    display_driver: Box<dyn DisplayBackend>,
+    input_driver: Box<dyn InputBackend>,
+    timer_hz: u32,            // Rate the delay and sound timers count down at
+    draw_cost: u32,           // Cycles DXYN takes
+    cycle_count: u64,         // Cycles executed since the machine was last reset
+    instruction_count: u64,   // Instructions executed since the machine was last reset
+    pause_after: Option<u64>, // Pause the main loop once this many instructions were executed
+    timer_phase: u32, // Accumulates timer_hz every cycle, the timers tick each time it passes the clock rate
+    frame_phase: u32, // Like timer_phase, but for the display refresh
+    entry_point: usize, // Where execution starts after a reset, the program origin unless configured otherwise
+    refresh_hz: u32, // Frames per second, each frame presents the display and is a vertical blank
+    display_dirty: bool, // The display changed since it was last presented
+    draw_count: u64, // Clears and draws executed, so callers can tell whether a step changed the display
+    busy_slowdown: bool, // Experimental: busy frames make the instructions of the next frame take longer
+    frame_draws: u32,    // Clears and draws in the current frame
+    busy_load: u32,      // Clears and draws in the previous frame, capped
+    slowdown_phase: u32, // Accumulates busy_load every instruction, each SLOWDOWN_DRAWS_PER_CYCLE is a cycle
+    vblank: bool,        // A frame ended since the last draw, used by the display wait quirk
+    present_each_draw: bool, // Show every clear and draw as it happens, including intermediate XOR-erase states
+    max_frameskip: u32,      // Frames in a row that may be skipped while behind real time
+    skipped_frames: u32,     // Frames skipped in a row so far
+    behind: bool, // Execution is running behind real time, e.g. because presenting is slow
+    defer_clear: bool, // Keep showing the previous frame after a clear until something is drawn
+    clear_pending: bool, // The display was cleared and nothing was drawn since
+    quirks: Quirks,
+    index_policy: IndexPolicy,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    empty_sprite_policy: EmptySpritePolicy,
+    vf_writes: u32, // Number of flag writes through set_flag, for self-checks in debug builds
+    instruction_pc: usize, // Address of the instruction being executed, for reporting faults
+    watchpoints: HashSet<usize>, // Addresses that halt execution when written to
+    halt_after: Option<CpuError>, // Set by a watchpoint write or a DXY0 error, returned at the end of the step
+    reported_faults: HashSet<usize>, // Addresses a fault was reported for, so each is only reported once
+    fault_overlay: bool, // Also show recovered faults in the window, for when the terminal isn't visible
+    rng: StdRng,
+    trace_output: Option<Box<dyn Write>>, // Receives a line per executed instruction when tracing
+    events: Option<EventLog>,             // Receives high-level events as JSON lines
+    vf_lint: Option<VfLint>,              // Warns about VF reads that may see a stale flag
+    timing_stats: Option<TimingStats>,    // Periodically prints how long frames take in real time
+    rom_watcher: Option<RomWatcher>,      // Reloads the ROM when its file changes
+    save_state_path: Option<String>,      // Where the save state hotkey writes the state to
+    snapshot_dir: Option<String>,         // Where a state is saved to whenever execution halts
+    console: Option<Console>,             // Debugger commands typed into the terminal
+    audio_driver: Option<Box<dyn AudioBackend>>, // Plays the beep while the sound timer runs
+    debug_poll_interval: u32, // Instructions run between checks for debugger commands, while not paused
+    debug_poll_countdown: u32, // Loop iterations left until the next check
+    paused: bool,
+    paint_mode: bool, // Debug aid: while paused, clicking the window toggles the pixel under the cursor
+    program: Vec<u8>, // The last program loaded, to start it over when looping
+    loop_delay: Option<time::Duration>, // Restart the program once it has been halted this long
+    halted_since: Option<time::Instant>, // When the program was first seen halted, while looping
+    zero_run: u32,    // Consecutive 0000 opcodes executed
+    waiting_for_key: bool, // FX0A found no key and will be retried
 }
 
 impl Processor {
-    pub fn new(disp: DisplayDriver, input: InputDriver) -> Self {
+    pub fn new(disp: impl DisplayBackend + 'static, input: impl InputBackend + 'static) -> Self {
         let mut ram = [0u8; CHIP8_MEMORY];
 
         // Load the font into memory.
@@ -41,363 +244,2898 @@ impl Processor {
             index_register: 0,
             pc: CHIP8_PROGRAM_MEMORY_START, // Program counter starts at 0x200 because 0x000-0x1FF stores the font
             sp: 0,
-            display_driver: disp,
-            input_driver: input,
+            display_driver: Box::new(disp),
+            input_driver: Box::new(input),
+            timer_hz: DEFAULT_TIMER_HZ,
+            draw_cost: DEFAULT_DRAW_COST,
+            cycle_count: 0,
+            instruction_count: 0,
+            pause_after: None,
+            timer_phase: 0,
+            frame_phase: 0,
+            entry_point: CHIP8_PROGRAM_MEMORY_START,
+            refresh_hz: DEFAULT_REFRESH_HZ,
+            display_dirty: false,
+            draw_count: 0,
+            busy_slowdown: false,
+            frame_draws: 0,
+            busy_load: 0,
+            slowdown_phase: 0,
+            vblank: false,
+            present_each_draw: false,
+            max_frameskip: DEFAULT_MAX_FRAMESKIP,
+            skipped_frames: 0,
+            behind: false,
+            defer_clear: false,
+            clear_pending: false,
+            quirks: Quirks::default(),
+            index_policy: IndexPolicy::Wrap,
+            unknown_opcode_policy: UnknownOpcodePolicy::Skip,
+            empty_sprite_policy: EmptySpritePolicy::Ignore,
+            vf_writes: 0,
+            instruction_pc: CHIP8_PROGRAM_MEMORY_START,
+            watchpoints: HashSet::new(),
+            halt_after: None,
+            reported_faults: HashSet::new(),
+            fault_overlay: true,
+            rng: StdRng::from_entropy(),
+            trace_output: None,
+            events: None,
+            vf_lint: None,
+            timing_stats: None,
+            rom_watcher: None,
+            save_state_path: None,
+            snapshot_dir: None,
+            console: None,
+            audio_driver: None,
+            debug_poll_interval: 1,
+            debug_poll_countdown: 0,
+            paused: false,
+            paint_mode: false,
+            program: Vec::new(),
+            loop_delay: None,
+            halted_since: None,
+            zero_run: 0,
+            waiting_for_key: false,
         }
     }
 
-    pub fn load_program(&mut self, prog_data: &[u8]) {
-        for (i, &byte) in prog_data.iter().enumerate() {
-            let address = CHIP8_PROGRAM_MEMORY_START + i;
-
-            if address >= CHIP8_MEMORY {
-                panic!("Program data too large to load in memory")
-            }
-
-            self.ram[address] = byte;
-        }
-
-        println!("Successfully loaded program into memory")
+    // Reseeds the random number generator used by CXNN so that runs can be reproduced
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
 
-    pub fn start(&mut self) {
-        // Sleep for 5 milliseconds
-
-        let sleep_duration = time::Duration::from_millis(5);
-        loop {
-            // Look for quit event
-            let input_key_code = self.input_driver.last_input();
-
-            let instruction = self.get_instruction();
-
-            self.decode_and_execute_instruction(instruction, input_key_code);
-            thread::sleep(sleep_duration)
-        }
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    fn push_addr(&mut self, address: usize) {
-        self.stack[self.sp] = address;
-        self.sp += 1;
+    pub fn set_index_policy(&mut self, policy: IndexPolicy) {
+        self.index_policy = policy;
     }
 
-    fn pop_addr(&mut self) -> usize {
-        self.sp -= 1;
-
-        self.stack[self.sp]
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.unknown_opcode_policy = policy;
     }
 
-    // Fetches instruction, which is 2 successive bytes in memory. Increments the program counter by 2 (to be ready for next instruction)
-    fn get_instruction(&mut self) -> u16 {
-        let instruction: u16 = (self.ram[self.pc] as u16) << 8 | self.ram[self.pc + 1] as u16;
-
-        self.pc += 2;
-        return instruction;
+    pub fn set_empty_sprite_policy(&mut self, policy: EmptySpritePolicy) {
+        self.empty_sprite_policy = policy;
     }
 
-    fn decode_and_execute_instruction(&mut self, instruction: u16, keycode: Option<u8>) {
-        let nibbles: (u16, u16, u16, u8) = (
-            (instruction & 0xF000) >> 12 as u8,
-            (instruction & 0x0F00) >> 8 as u8,
-            (instruction & 0x00F0) >> 4 as u8,
-            (instruction & 0x000F) as u8,
-        );
-
-        let nnn = (instruction & 0x0FFF) as usize; // Last 3 nibbles
-        let nn: u8 = (instruction & 0x00FF) as u8; // Last byte
-        let x: usize = nibbles.1 as usize; // Second nibble
-        let y: usize = nibbles.2 as usize; // Third nibble
-        let n: usize = nibbles.3 as usize; // Fourth nibble
-
-        match nibbles {
-            (0x00, 0x00, 0x0e, 0x00) => {
-                // Clear screen
-                self.instruction_clear_screen();
-            }
-            (0x00, 0x00, 0x0e, 0x0e) => {
-                // Return from subroutine
-                self.instruction_return();
-            }
-            (0x06, _, _, _) => {
-                // Set variable address value
-                self.instruction_set(x, nn);
-            }
-            (0x07, _, _, _) => {
-                // Add to variable address
-                self.instruction_add(x, nn);
-            }
-            (0x01, _, _, _) => {
-                // Jump to address
-                self.instruction_jmp(nnn);
-            }
-            (0x02, _, _, _) => {
-                // Call Subroutine
-                self.instruction_call_subroutine(nnn);
-            }
-            (0x0A, _, _, _) => {
-                // Set index register
-                self.instruction_set_index(nnn);
-            }
-            (0x0F, _, 0x01, 0x0E) => self.instruction_add_index(x),
-            (0x0B, _, _, _) => self.instruction_jump_with_offset(nnn),
-            (0x0C, _, _, _) => self.instruction_random(x, nn),
-            (0x0D, _, _, _) => {
-                // Display and Draw
-                self.instruction_draw_display(x, y, n);
-            }
-            (0x03, _, _, _) => {
-                // Skip one instruction if VX == NN
-                self.instruction_skip_equal(x, nn)
-            }
-            (0x0E, _, 0x09, 0x0E) => {
-                self.instruction_skip_key(x, keycode.unwrap());
-            }
-            (0x0E, _, 0x0A, 0x01) => {
-                self.instruction_skip_not_key(x, keycode.unwrap());
-            }
-            (0x0F, _, 0x00, 0x0A) => self.instruction_get_key(x, keycode),
-            (0x04, _, _, _) => self.instruction_skip_not_equal(x, nn),
-            (0x05, _, _, 0x00) => self.instruction_skip_register_equal(x, y),
-            (0x09, _, _, 0x00) => self.instruction_skip_register_not_equal(x, y),
-            (0x08, _, _, 0x00) => self.instruction_alu_set(x, y),
-            (0x08, _, _, 0x01) => self.instruction_alu_or(x, y),
-            (0x08, _, _, 0x02) => self.instruction_alu_and(x, y),
-            (0x08, _, _, 0x03) => self.instruction_alu_xor(x, y),
-            (0x08, _, _, 0x04) => self.instruction_alu_add(x, y),
-            (0x08, _, _, 0x05) => self.instruction_alu_subtract(x, y),
-            (0x08, _, _, 0x07) => self.instruction_alu_subtract(y, x),
-            (0x08, _, _, 0x06) => self.instruction_alu_shift(x, y, false),
-            (0x08, _, _, 0x0E) => self.instruction_alu_shift(x, y, true),
-            (0x0F, _, 0x02, 0x09) => self.instruction_font_character(x),
-            (0x0F, _, 0x03, 0x03) => self.instruction_bcd_convert(x),
+    pub fn set_fault_overlay(&mut self, fault_overlay: bool) {
+        self.fault_overlay = fault_overlay;
+    }
 
-            _ => println!("0x{:04X} Not supported yet!", instruction),
+    /*
+       Programs are always loaded at 0x200, but execution can start elsewhere, e.g. to run code that was placed
+       further into a ROM or test a routine on its own. Takes effect right away and after every reset. A whole
+       instruction has to fit at the address, so the last byte of memory is rejected along with anything past it.
+    */
+    pub fn set_entry_point(&mut self, address: usize) -> Result<(), CpuError> {
+        if address >= CHIP8_MEMORY - 1 {
+            return Err(CpuError::EntryPointOutOfRange { address });
         }
-    }
 
-    fn instruction_jmp(&mut self, address: usize) {
+        self.entry_point = address;
         self.pc = address;
+        Ok(())
     }
 
-    fn instruction_jump_with_offset(&mut self, address: usize) {
-        // TODO: AMBIGUOUS INSTRUCTION -- ADD CONFIG FOR THIS TO SUPPORT CHIP-48/SUPER-CHIP
-        self.instruction_jmp(address + self.var_registers[0x00] as usize);
+    pub fn set_timer_hz(&mut self, hz: u32) {
+        self.timer_hz = hz;
     }
 
-    fn instruction_clear_screen(&mut self) {
-        for i in 0..self.display.len() {
-            for j in 0..self.display[i].len() {
-                self.display[i][j] = 0;
-            }
+    /*
+       Experimental: changes how often frames end, which is when changes to the display are presented and what the
+       display wait quirk waits for. Displays of the day refreshed at 60Hz, which is what ROMs expect; other rates
+       are for seeing how the refresh rate affects flicker and feel. The timers keep their own rate. There is at
+       most a frame per cycle, so higher rates are capped at the clock speed.
+    */
+    pub fn set_refresh_hz(&mut self, hz: u32) {
+        self.refresh_hz = hz.min(CHIP8_CLOCK_HZ);
+
+        if let Some(timing_stats) = &mut self.timing_stats {
+            *timing_stats = TimingStats::new(self.refresh_hz);
         }
     }
 
-    fn instruction_draw_display(&mut self, vx: usize, vy: usize, height: usize) {
-        let row = self.var_registers[vy] as usize;
-        let col = self.var_registers[vx] as usize;
-
-        self.var_registers[CHIP8_VF_INDEX] = 0;
-
-        for i in 0..height {
-            let sprite_row = self.ram[self.index_register + i];
+    /*
+       On the VIP drawing a sprite took far longer than most instructions, and some ROMs rely on that to pace their
+       animations. Every instruction costs a single cycle by default, which keeps the speed most ROMs are tuned for
+       today. A higher draw cost makes DXYN take that many cycles of timer and frame time, slowing down ROMs that
+       draw a lot relative to their timers. Draws waiting for the vertical blank (display wait quirk) only cost a
+       single cycle per attempt.
+    */
+    pub fn set_draw_cost(&mut self, cycles: u32) {
+        self.draw_cost = cycles;
+    }
 
-            // For each bit in the orw
-            for j in 0..8 {
-                let bit = (sprite_row >> j) & 1;
+    /*
+       Experimental. The original interpreter spent part of every frame on drawing, so busy scenes ran slower. With
+       this enabled every clear or draw in a frame adds 1/16th of a cycle to each instruction in the next frame, up
+       to 2 extra cycles per instruction at 32 draws. Timers keep their pace, so fewer instructions run per frame.
+    */
+    pub fn enable_busy_slowdown(&mut self) {
+        self.busy_slowdown = true;
+    }
 
-                let pixel_screen = self.display[(row + i) % 32][(col + 7 - j) % 64];
+    /*
+       By default changes to the display are coalesced and presented once per 60Hz frame, which hides the flicker
+       of sprites being erased and redrawn. Presenting every draw instead shows the display the way the hardware did,
+       which helps authors debug their draw/erase sequencing.
+    */
+    pub fn set_present_each_draw(&mut self, present_each_draw: bool) {
+        self.present_each_draw = present_each_draw;
+    }
 
-                if bit == 1 && pixel_screen == 1 {
-                    // We're going to unset a pixel, so set flag in VF
-                    self.var_registers[CHIP8_VF_INDEX] = 1;
-                }
+    /*
+       When the emulator falls behind real time, e.g. on a slow machine where presenting takes most of a frame, up
+       to this many frames in a row are left unpresented so that the program keeps running at its normal speed. 0
+       never skips a frame, letting the program slow down instead.
+    */
+    pub fn set_max_frameskip(&mut self, frames: u32) {
+        self.max_frameskip = frames;
+    }
 
-                self.display[(row + i) % 32][(col + 7 - j) % 64] ^= bit;
-            }
-        }
+    /*
+       Many ROMs clear the screen and redraw everything each frame, which flickers whenever a frame ends between the
+       clear and the redraw. Deferring clears keeps the previous frame on screen until the first draw after a clear,
+       like double buffering. Real hardware showed the blank screen, so this is off by default.
+    */
+    pub fn set_defer_clear(&mut self, defer_clear: bool) {
+        self.defer_clear = defer_clear;
+    }
 
-        self.display_driver.draw(&self.display);
+    /*
+       Starts the program over once it has been halted for the given delay, for demos and kiosks that should keep
+       running unattended. See is_halted for what counts as halted.
+    */
+    pub fn enable_loop(&mut self, delay: time::Duration) {
+        self.loop_delay = Some(delay);
     }
 
-    fn instruction_call_subroutine(&mut self, address: usize) {
-        self.push_addr(self.pc);
-        self.pc = address;
+    pub fn enable_vf_lint(&mut self) {
+        self.vf_lint = Some(VfLint::new());
     }
 
-    fn instruction_return(&mut self) {
-        let ret_address = self.pop_addr();
-        self.pc = ret_address;
+    pub fn enable_timing_stats(&mut self) {
+        self.timing_stats = Some(TimingStats::new(self.refresh_hz));
     }
 
-    fn instruction_set(&mut self, register: usize, value: u8) {
-        self.var_registers[register] = value;
+    pub fn set_rom_watcher(&mut self, rom_watcher: RomWatcher) {
+        self.rom_watcher = Some(rom_watcher);
     }
 
-    fn instruction_add(&mut self, register: usize, value: u8) {
-        // ADD WITHOUT CARRY FLAG
+    // Makes start pause once the program executed exactly this many instructions, e.g. to inspect a given moment
+    pub fn set_pause_after(&mut self, instructions: u64) {
+        self.pause_after = Some(instructions);
+    }
 
-        let (value, _) = self.var_registers[register].overflowing_add(value);
-        self.var_registers[register] = value;
+    // Halts execution after an instruction writes to the address, even if the value doesn't change
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address % CHIP8_MEMORY);
     }
 
-    fn instruction_set_index(&mut self, value: usize) {
-        self.index_register = value;
+    /*
+       Checking for debugger commands between every pair of instructions slows execution down noticeably, so while
+       running they can be checked for less often. While paused they are always checked for.
+    */
+    pub fn set_debug_poll_interval(&mut self, instructions: u32) {
+        self.debug_poll_interval = instructions.max(1);
+        self.debug_poll_countdown = 0;
     }
 
-    fn instruction_add_index(&mut self, register: usize) {
-        let mut index_value = self.index_register;
+    pub fn set_audio_driver(&mut self, audio: impl AudioBackend + 'static) {
+        self.audio_driver = Some(Box::new(audio));
+    }
 
-        index_value += self.var_registers[register] as usize;
+    pub fn enable_debug_console(&mut self) {
+        self.console = Some(Console::new());
+    }
 
-        if index_value >= 0x100 {
-            // For compatibility -- (kind of) overflow but not really
-            self.var_registers[CHIP8_VF_INDEX] = 1;
-        }
+    pub fn set_save_state_path(&mut self, path: &str) {
+        self.save_state_path = Some(String::from(path));
+    }
 
-        self.index_register = index_value
+    /*
+       Saves a state to the directory every time execution halts, on a watchpoint or an unknown opcode with the halt
+       policy, so the conditions can be looked at later, e.g. by loading the state. The files are named after when
+       and where execution halted: break-<milliseconds since the Unix epoch>-<PC in hex>.state.
+    */
+    pub fn set_snapshot_dir(&mut self, dir: &str) {
+        self.snapshot_dir = Some(String::from(dir));
     }
 
-    fn instruction_skip_equal(&mut self, register: usize, value: u8) {
-        if self.var_registers[register] == value {
-            self.pc += 2
-        }
+    pub fn enable_paint_mode(&mut self) {
+        self.paint_mode = true;
     }
 
-    fn instruction_skip_not_equal(&mut self, register: usize, value: u8) {
-        if self.var_registers[register] != value {
-            self.pc += 2
-        }
+    pub fn set_trace_output(&mut self, output: Box<dyn Write>) {
+        self.trace_output = Some(output);
     }
 
-    fn instruction_skip_key(&mut self, register: usize, keycode: u8) {
-        if self.var_registers[register] == keycode {
-            self.pc += 2
-        }
+    pub fn set_event_output(&mut self, output: Box<dyn Write>) {
+        self.events = Some(EventLog::new(output));
     }
 
-    fn instruction_skip_not_key(&mut self, register: usize, keycode: u8) {
-        if self.var_registers[register] != keycode {
-            self.pc += 2
+    fn emit(&mut self, event: Event) {
+        if let Some(events) = &mut self.events {
+            if events.emit(event).is_err() {
+                println!("Failed to write event, events disabled");
+                self.events = None;
+            }
         }
     }
 
-    fn instruction_get_key(&mut self, register: usize, keycode: Option<u8>) {
-        // Blocks until a key is pressed
-
-        if keycode.is_none() {
-            self.pc -= 2;
-            return;
-        }
+    pub fn memory(&self) -> &[u8; CHIP8_MEMORY] {
+        &self.ram
+    }
 
-        self.var_registers[register] = keycode.unwrap();
+    pub fn framebuffer(&self) -> &[[u8; 64]; 32] {
+        &self.display
     }
 
-    fn instruction_skip_register_equal(&mut self, vx_register: usize, vy_register: usize) {
-        if self.var_registers[vx_register] == self.var_registers[vy_register] {
-            self.pc += 2;
-        }
+    // V0 to VF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.var_registers
     }
 
-    fn instruction_skip_register_not_equal(&mut self, vx_register: usize, vy_register: usize) {
-        if self.var_registers[vx_register] != self.var_registers[vy_register] {
-            self.pc += 2;
-        }
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
     }
 
-    fn instruction_random(&mut self, vx_register: usize, value: u8) {
-        // Randomly generates a number, ANDs it with value, and stores it in vx register
-        let random_value = rand::random::<u8>();
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
 
-        self.var_registers[vx_register] = random_value & value
+    // Whether the beep should be heard, it stops as soon as the sound timer was decremented to 0
+    pub fn sound_playing(&self) -> bool {
+        self.sound_timer > 0
     }
 
-    fn instruction_alu_set(&mut self, vx_register: usize, vy_register: usize) {
-        // Set value of vx register to value of vy register
-        self.var_registers[vx_register] = self.var_registers[vy_register]
+    pub fn cycles(&self) -> u64 {
+        self.cycle_count
     }
 
-    fn instruction_alu_or(&mut self, vx_register: usize, vy_register: usize) {
-        // Binary OR
-        // Or vx register value with vy register value and store in vx register
-        self.var_registers[vx_register] |= self.var_registers[vy_register]
+    pub fn instructions(&self) -> u64 {
+        self.instruction_count
     }
 
-    fn instruction_alu_and(&mut self, vx_register: usize, vy_register: usize) {
-        // Binary AND
-        // And vx register value with vy register value and store in vx register
-        self.var_registers[vx_register] &= self.var_registers[vy_register]
+    pub fn pc(&self) -> usize {
+        self.pc
     }
 
-    fn instruction_alu_xor(&mut self, vx_register: usize, vy_register: usize) {
-        // Logical XOR
-        // Xor vx register value with vy register value and store in vx register
-        self.var_registers[vx_register] ^= self.var_registers[vy_register]
+    pub fn index(&self) -> usize {
+        self.index_register
     }
 
-    fn instruction_alu_add(&mut self, vx_register: usize, vy_register: usize) {
-        // Add vx register value with vy register value and store in vx register
-        // if overflow occurred, set VF to 1, else set it to 0
-        let (value, overflow) =
-            self.var_registers[vx_register].overflowing_add(self.var_registers[vy_register]);
+    // Sets the index register from outside the program (tests, debuggers), applying the index policy
+    pub fn set_index(&mut self, value: usize) -> Result<(), CpuError> {
+        self.index_register = match self.index_policy {
+            _ if value < CHIP8_MEMORY => value,
+            IndexPolicy::Wrap => value % CHIP8_MEMORY,
+            IndexPolicy::Clamp => CHIP8_MEMORY - 1,
+            IndexPolicy::Error => return Err(CpuError::IndexOutOfRange { value }),
+        };
 
-        self.var_registers[vx_register] = value;
-        if overflow {
-            self.var_registers[CHIP8_VF_INDEX] = 1;
-        } else {
-            self.var_registers[CHIP8_VF_INDEX] = 0;
-        }
+        Ok(())
     }
 
-    fn instruction_alu_subtract(&mut self, vx_register: usize, vy_register: usize) {
-        let (vx_value, vy_value) = (
-            self.var_registers[vx_register],
-            self.var_registers[vy_register],
-        );
+    // Returns the machine to its power-on state, keeping its configuration. The program has to be loaded again.
+    pub fn reset(&mut self) {
+        self.ram = [0; CHIP8_MEMORY];
+        self.ram[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+
+        self.display = [[0; 64]; 32];
+        self.stack = [0; 16];
+        self.var_registers = [0; 16];
+        self.pc = self.entry_point;
+        self.index_register = 0;
+        self.sp = 0;
+        self.sound_timer = 0;
+        self.delay_timer = 0;
+        self.timer_phase = 0;
+        self.frame_phase = 0;
+        self.cycle_count = 0;
+        self.instruction_count = 0;
+        self.frame_draws = 0;
+        self.busy_load = 0;
+        self.slowdown_phase = 0;
+        self.display_dirty = true;
+        self.clear_pending = false;
+        self.vblank = false;
+        self.reported_faults.clear();
+        self.halted_since = None;
+        self.zero_run = 0;
+        self.waiting_for_key = false;
+
+        self.emit(Event::Reset);
+    }
 
-        if vx_value > vy_value {
-            self.var_registers[CHIP8_VF_INDEX] = 1; // Set VF before subtraction
-        }
+    pub fn load_program(&mut self, prog_data: &[u8]) {
+        for (i, &byte) in prog_data.iter().enumerate() {
+            let address = CHIP8_PROGRAM_MEMORY_START + i;
 
-        let (value, underflow) = vx_value.overflowing_sub(vy_value);
-        self.var_registers[vx_register] = value;
-        if underflow {
-            self.var_registers[CHIP8_VF_INDEX] = 0;
+            if address >= CHIP8_MEMORY {
+                panic!("Program data too large to load in memory")
+            }
+
+            self.ram[address] = byte;
         }
+        self.program = prog_data.to_vec();
+
+        println!("Successfully loaded program into memory");
+        self.emit(Event::RomLoaded {
+            size: prog_data.len(),
+        });
     }
 
-    fn instruction_alu_shift(&mut self, vx_register: usize, vy_register: usize, left_shift: bool) {
-        //TODO: OPTIONAL_CONFIGURABLE -- SET VX VALUE TO VY VALUE
-        self.var_registers[vx_register] = self.var_registers[vy_register];
+    /*
+       Loads the opcodes at the program origin and executes them until the program counter leaves them, which makes
+       it easy to try out what a handful of instructions do without assembling a ROM. No keys are fed to the program.
+    */
+    pub fn run_opcodes(&mut self, ops: &[u16]) {
+        let program: Vec<u8> = ops.iter().flat_map(|op| op.to_be_bytes()).collect();
+        let program_end = CHIP8_PROGRAM_MEMORY_START + program.len();
 
-        let mut vx_value = self.var_registers[vx_register];
+        self.load_program(&program);
+        self.pc = CHIP8_PROGRAM_MEMORY_START;
 
-        let vf_value: u8;
+        for _ in 0..RUN_OPCODES_MAX_CYCLES {
+            if !(CHIP8_PROGRAM_MEMORY_START..program_end).contains(&self.pc) {
+                return;
+            }
 
-        if left_shift {
-            // left shift
-            vf_value = self.var_registers[vx_register] & 0x80;
-            vx_value <<= 1;
+            if let Err(err) = self.step(None) {
+                println!("Halted: {}", err);
+                return;
+            }
+        }
+
+        println!(
+            "Stopped after {} cycles without leaving the opcodes",
+            RUN_OPCODES_MAX_CYCLES
+        );
+    }
+
+    // Prints the registers on a single line, e.g. to show the outcome of run_opcodes
+    pub fn print_registers(&self) {
+        let registers: Vec<String> = self
+            .var_registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X}={:02X}", i, value))
+            .collect();
+
+        println!(
+            "{} I={:03X} PC={:03X} DT={:02X} ST={:02X}",
+            registers.join(" "),
+            self.index_register,
+            self.pc,
+            self.delay_timer,
+            self.sound_timer
+        );
+    }
+
+    pub fn start(&mut self) {
+        // Each cycle takes 5 milliseconds of real time
+
+        let sleep_duration = time::Duration::from_millis(1000 / CHIP8_CLOCK_HZ as u64);
+        let mut next_cycle = time::Instant::now();
+        loop {
+            // Instructions that take several cycles, like costly draws, are given that many cycles of real time
+            let cycles = self.tick();
+            next_cycle += sleep_duration * cycles;
+
+            if let Some(duration) = self.keep_pace(&mut next_cycle, time::Instant::now()) {
+                thread::sleep(duration);
+            }
+        }
+    }
+
+    /*
+       Returns how long to sleep until the next cycle is due. When it's already overdue the emulator is behind and
+       skips frames to catch up, unless it's too far behind to ever catch up, in which case it carries on from now.
+    */
+    fn keep_pace(
+        &mut self,
+        next_cycle: &mut time::Instant,
+        now: time::Instant,
+    ) -> Option<time::Duration> {
+        if *next_cycle > now {
+            self.behind = false;
+            Some(*next_cycle - now)
+        } else if now - *next_cycle > MAX_LAG {
+            self.behind = false;
+            *next_cycle = now;
+            None
         } else {
-            // right shift
-            vf_value = self.var_registers[vx_register] & 0x01;
-            vx_value >>= 1;
+            self.behind = true;
+            None
+        }
+    }
+
+    /*
+       One pass of the main loop without the sleeping: handles input and debugger commands and executes up to one
+       instruction. Returns the cycles it took, which is at least one even while paused.
+    */
+    fn tick(&mut self) -> u32 {
+        self.reload_if_changed();
+
+        self.debug_poll_countdown = self.debug_poll_countdown.saturating_sub(1);
+        if self.debug_poll_countdown == 0 || self.paused {
+            self.debug_poll_countdown = self.debug_poll_interval;
+            self.run_debug_commands();
         }
 
-        self.var_registers[CHIP8_VF_INDEX] = vf_value;
-        self.var_registers[vx_register] = vx_value;
+        // Look for quit event
+        let input_key_code = match self.input_driver.last_input() {
+            Some(Input::Key(keycode)) => Some(keycode),
+            Some(Input::Pause) => {
+                self.paused = !self.paused;
+
+                if let Some(timing_stats) = &mut self.timing_stats {
+                    timing_stats.restart();
+                }
+                println!("{}", if self.paused { "Paused" } else { "Resumed" });
+                self.emit(if self.paused {
+                    Event::Paused
+                } else {
+                    Event::Resumed
+                });
+                None
+            }
+            Some(Input::ToggleInvert) => {
+                self.display_driver.toggle_inverted();
+                self.draw_display();
+                None
+            }
+            Some(Input::ToggleDisplayWait) => {
+                self.quirks.display_wait = !self.quirks.display_wait;
+
+                let state = if self.quirks.display_wait {
+                    "on"
+                } else {
+                    "off"
+                };
+                println!("Display wait quirk {}", state);
+                self.display_driver
+                    .show_message(&format!("Display wait {}", state));
+                None
+            }
+            Some(Input::SaveState) => {
+                self.save_state_to_file();
+                None
+            }
+            Some(Input::StepOut) => {
+                if self.paused {
+                    self.step_out();
+                }
+                None
+            }
+            Some(Input::Click { x, y }) => {
+                if self.paused && self.paint_mode {
+                    if let Some((col, row)) = self.display_driver.pixel_at(x, y) {
+                        self.toggle_pixel(col, row);
+                    }
+                }
+                None
+            }
+            None => None,
+        };
+
+        // A fresh press is handed to the program once, even if other input came in after it
+        let input_key_code = self.input_driver.take_latched_key().or(input_key_code);
+
+        // Restarting resets the cycle count, so it goes first
+        if !self.paused {
+            self.restart_if_halted();
+        }
+
+        let cycles_before = self.cycle_count;
+
+        if self.pause_after == Some(self.instruction_count) {
+            self.pause_after = None;
+            self.paused = true;
+
+            let message = format!("Paused after {} instructions", self.instruction_count);
+            println!("{}", message);
+            self.display_driver.show_message(&message);
+            self.present_display();
+            self.emit(Event::Paused);
+        }
+
+        if !self.paused {
+            if let Err(err) = self.step(input_key_code) {
+                let message = format!("Halted: {}", err);
+                println!("{}", message);
+                self.display_driver.show_message(&message);
+                self.paused = true;
+
+                let reason = err.to_string();
+                self.emit(Event::Halted {
+                    pc: self.pc,
+                    reason: &reason,
+                });
+                self.save_snapshot();
+            }
+        }
+
+        self.update_beep();
+
+        (self.cycle_count - cycles_before).max(1) as u32
     }
 
-    fn instruction_font_character(&mut self, vx_register: usize) {
-        self.index_register = (self.var_registers[vx_register] as usize) * 5
+    /*
+       The beep follows the sound timer, except that it's silenced while paused (the timer is frozen then, it would
+       drone on) and while the window is in the background. Quitting ends the process, taking the beep with it.
+    */
+    fn update_beep(&mut self) {
+        let beeping = self.sound_playing() && !self.paused && self.input_driver.has_focus();
+
+        if let Some(audio_driver) = &mut self.audio_driver {
+            audio_driver.set_beeping(beeping);
+        }
     }
 
-    fn instruction_bcd_convert(&mut self, vx_register: usize) {
-        // Binary-coded decimal conversion
-        let mut vx_value = self.var_registers[vx_register];
+    fn run_debug_commands(&mut self) {
+        while let Some(line) = self.console.as_ref().and_then(Console::poll) {
+            match Command::parse(&line) {
+                Ok(command) => self.run_debug_command(command),
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
 
-        for i in (0..3).rev() {
-            let digit = vx_value % 10;
-            self.ram[self.index_register + i] = digit;
-            vx_value /= 10;
+    fn run_debug_command(&mut self, command: Command) {
+        match command {
+            Command::Search(pattern) => {
+                let addresses = debugger::search(&self.ram, &pattern);
+                println!("Memory: {}", debugger::format_addresses(&addresses));
+
+                if let [value] = pattern[..] {
+                    let registers: Vec<String> = (0..16)
+                        .filter(|&i| self.var_registers[i] == value)
+                        .map(|i| format!("V{:X}", i))
+                        .collect();
+
+                    if registers.is_empty() {
+                        println!("Registers: none");
+                    } else {
+                        println!("Registers: {}", registers.join(" "));
+                    }
+                }
+            }
+            Command::Watch(addresses) => {
+                for &address in &addresses {
+                    self.add_watchpoint(address);
+                }
+                println!("Watching {}", debugger::format_addresses(&addresses));
+            }
+            /*
+               The range was checked while parsing. The bytes are stored like the program would store them, so e.g.
+               filling the VIP display memory shows up on the display, but filling doesn't hit watchpoints.
+            */
+            Command::Fill(range, byte) => {
+                println!(
+                    "Filled 0x{:03X}-0x{:03X} with 0x{:02X}",
+                    range.start,
+                    range.end - 1,
+                    byte
+                );
+
+                let halt_after = self.halt_after.take();
+                for address in range {
+                    self.write_ram(address, byte);
+                }
+                self.halt_after = halt_after;
+            }
+            Command::Poll(instructions) => {
+                self.set_debug_poll_interval(instructions);
+                println!("Checking for commands every {} instructions", instructions);
+            }
+            Command::Help => println!("{}", debugger::HELP),
+        }
+    }
+
+    fn save_state_to_file(&mut self) {
+        let message = match &self.save_state_path {
+            Some(path) => match self.save_state(path) {
+                Ok(()) => format!("Saved state to {}", path),
+                Err(err) => err.to_string(),
+            },
+            None => String::from("No save state file, pass one with --save-state"),
+        };
+
+        println!("{}", message);
+        self.display_driver.show_message(&message);
+    }
+
+    fn save_snapshot(&mut self) {
+        let Some(dir) = &self.snapshot_dir else {
+            return;
+        };
+
+        let millis = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        let path = Path::new(dir).join(format!("break-{}-{:03X}.state", millis, self.pc));
+        let path = path.to_string_lossy().into_owned();
+
+        let result = fs::create_dir_all(dir)
+            .map_err(|source| StateError::Io {
+                path: dir.clone(),
+                source,
+            })
+            .and_then(|()| self.save_state(&path));
+
+        match result {
+            Ok(()) => println!("Saved snapshot to {}", path),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    // Restarts the machine with the watched ROM when its file changed, carrying on with the old one if it can't be read
+    fn reload_if_changed(&mut self) {
+        let Some(rom_watcher) = &mut self.rom_watcher else {
+            return;
+        };
+
+        let message = match rom_watcher.poll() {
+            None => return,
+            Some(Ok(rom)) => {
+                self.reset();
+                self.load_program(rom.bytes());
+                String::from("ROM reloaded")
+            }
+            Some(Err(err)) => format!("Keeping the old ROM, reload failed: {}", err),
+        };
+
+        println!("{}", message);
+        self.display_driver.show_message(&message);
+    }
+
+    // Starts the program over when looping and it has been halted for long enough
+    fn restart_if_halted(&mut self) {
+        let Some(delay) = self.loop_delay else {
+            return;
+        };
+
+        if !self.is_halted() {
+            self.halted_since = None;
+            return;
+        }
+
+        let halted_since = *self.halted_since.get_or_insert_with(time::Instant::now);
+        if halted_since.elapsed() >= delay {
+            let program = std::mem::take(&mut self.program);
+            self.reset();
+            self.load_program(&program);
+            println!("Program ended, restarting it");
+        }
+    }
+
+    /*
+       Whether the program can't do anything anymore: it's jumping to itself with both timers stopped, which is how
+       most ROMs end, or it ran off into a long stretch of empty memory. Programs waiting for a key or a timer aren't
+       halted, since they read the keypad or a timer while looping.
+    */
+    pub fn is_halted(&self) -> bool {
+        let jump_to_self = self.peek_instruction() == 0x1000 | self.pc as u16;
+
+        self.zero_run >= HALT_ZERO_RUN
+            || (jump_to_self && self.delay_timer == 0 && self.sound_timer == 0)
+    }
+
+    /*
+       Flips a single pixel of the framebuffer, e.g. to hand craft a test pattern while paused. Coordinates off the
+       display are ignored, so callers don't have to check them first.
+    */
+    pub fn toggle_pixel(&mut self, x: usize, y: usize) {
+        let Some(pixel) = self.display.get_mut(y).and_then(|row| row.get_mut(x)) else {
+            return;
+        };
+
+        *pixel ^= 1;
+        self.draw_display();
+    }
+
+    /*
+       Steps until the PC leaves the range, e.g. to skip over a loop or subroutine, and returns whether it did
+       within max_cycles. No keys are fed to the program. Halting on an unknown opcode stops early.
+    */
+    pub fn run_until_pc_outside(&mut self, range: Range<usize>, max_cycles: usize) -> bool {
+        for _ in 0..max_cycles {
+            if !range.contains(&self.pc) {
+                return true;
+            }
+
+            if self.step(None).is_err() {
+                return false;
+            }
+        }
+
+        !range.contains(&self.pc)
+    }
+
+    /*
+       Steps until an instruction clears or draws to the display, e.g. to take a screenshot of the first thing a ROM
+       shows. Returns whether that happened within max_cycles. No keys are fed to the program.
+    */
+    pub fn run_until_draw(&mut self, max_cycles: usize) -> bool {
+        let draw_count = self.draw_count;
+
+        for _ in 0..max_cycles {
+            if self.step(None).is_err() {
+                return false;
+            }
+
+            if self.draw_count != draw_count {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /*
+       The range of the subroutine the PC is in: from the target of the call on top of the stack up to and
+       including the first return found after the PC. None outside of a subroutine.
+    */
+    fn current_subroutine(&self) -> Option<Range<usize>> {
+        if self.sp == 0 {
+            return None;
+        }
+
+        let call_address = self.stack[self.sp - 1].checked_sub(2)?;
+        let call = u16::from_be_bytes([self.ram[call_address], self.ram[call_address + 1]]);
+        let entry = (call & 0x0FFF) as usize;
+
+        let mut address = self.pc.max(entry);
+        while address + 1 < CHIP8_MEMORY {
+            if self.ram[address] == 0x00 && self.ram[address + 1] == 0xEE {
+                return Some(entry..address + 2);
+            }
+
+            address += 2;
+        }
+
+        None
+    }
+
+    // Debug aid bound to a hotkey: runs the rest of the current subroutine and stays paused where it returned to
+    fn step_out(&mut self) {
+        let message = match self.current_subroutine() {
+            Some(range) if self.run_until_pc_outside(range.clone(), STEP_OUT_MAX_CYCLES) => {
+                format!("Stepped out to 0x{:03X}", self.pc)
+            }
+            Some(_) => format!(
+                "Still in the subroutine after {} cycles, stopped at 0x{:03X}",
+                STEP_OUT_MAX_CYCLES, self.pc
+            ),
+            None => String::from("Not in a subroutine"),
+        };
+
+        println!("{}", message);
+        self.display_driver.show_message(&message);
+        self.present_display();
+    }
+
+    /*
+       Executes a single instruction, returning the address it was fetched from and its opcode. Fails when halting
+       on unknown opcodes, in which case the PC is left pointing at the unknown instruction, and after an instruction
+       that wrote to a watchpoint or a DXY0 with the error policy, which is executed in full.
+    */
+    pub fn step(&mut self, keycode: Option<u8>) -> Result<TraceEntry, CpuError> {
+        let was_waiting_for_key = std::mem::replace(&mut self.waiting_for_key, false);
+        let pc = self.pc;
+        self.instruction_pc = pc;
+        let instruction = self.get_instruction();
+
+        // Retrying FX0A only lets time pass, the instruction was already checked and counted towards a run of 0000s
+        if let (false, Some(vf_lint)) = (was_waiting_for_key, &mut self.vf_lint) {
+            vf_lint.check(pc, instruction);
+        }
+
+        // Operands are resolved before executing, so the trace shows the values the instruction worked with
+        let disassembly = match self.trace_output {
+            Some(_) => self.disassemble(instruction),
+            None => None,
+        };
+
+        if !was_waiting_for_key {
+            self.zero_run = if instruction == 0 {
+                self.zero_run.saturating_add(1)
+            } else {
+                0
+            };
+        }
+
+        let vf_writes = self.vf_writes;
+
+        if let Err(err) = self.decode_and_execute_instruction(instruction, keycode) {
+            match self.unknown_opcode_policy {
+                UnknownOpcodePolicy::Skip => {
+                    println!("{}", err);
+                    self.emit(Event::UnknownOpcode {
+                        pc,
+                        opcode: instruction,
+                    });
+                }
+                UnknownOpcodePolicy::Halt => {
+                    self.pc = pc;
+                    return Err(err);
+                }
+                UnknownOpcodePolicy::Panic => panic!("{}", err),
+            }
+        }
+
+        let flag_writes = self.vf_writes.wrapping_sub(vf_writes);
+        debug_assert!(
+            expected_flag_writes(instruction, self.pc == pc).contains(&flag_writes),
+            "{:04X} at 0x{:03X} wrote a flag to VF {} times",
+            instruction,
+            pc,
+            flag_writes
+        );
+
+        let mut cycles = if instruction & 0xF000 == 0xD000 && self.pc != pc {
+            self.draw_cost
+        } else {
+            1
+        };
+
+        if self.busy_slowdown {
+            self.slowdown_phase += self.busy_load;
+            cycles += self.slowdown_phase / SLOWDOWN_DRAWS_PER_CYCLE;
+            self.slowdown_phase %= SLOWDOWN_DRAWS_PER_CYCLE;
+        }
+
+        for _ in 0..cycles {
+            self.advance_timers();
+            self.advance_frame();
+        }
+        self.cycle_count += cycles as u64;
+
+        let entry = TraceEntry {
+            pc,
+            opcode: instruction,
+        };
+
+        // FX0A is counted and traced once, when it gets its key
+        if self.waiting_for_key {
+            return Ok(entry);
+        }
+        self.instruction_count += 1;
+
+        if let Some(output) = &mut self.trace_output {
+            let written = match disassembly {
+                Some(disassembly) => writeln!(output, "{} {}", entry, disassembly),
+                None => writeln!(output, "{}", entry),
+            };
+
+            if written.is_err() {
+                println!("Failed to write trace, tracing disabled");
+                self.trace_output = None;
+            }
+        }
+
+        if let Some(hit) = self.halt_after.take() {
+            if let CpuError::Watchpoint { pc, address, value } = hit {
+                self.emit(Event::Watchpoint { pc, address, value });
+            }
+            return Err(hit);
+        }
+
+        Ok(entry)
+    }
+
+    // The assembly of an instruction followed by the current values of its operands, e.g. "ADD V1, V2 ; V1=03 V2=05"
+    fn disassemble(&self, instruction: u16) -> Option<String> {
+        let (mnemonic, operands) = disasm::disassemble(instruction, &self.quirks)?;
+
+        if operands.is_empty() {
+            return Some(mnemonic);
+        }
+
+        let values: Vec<String> = operands
+            .iter()
+            .map(|operand| match *operand {
+                Operand::Register(register) => {
+                    format!("V{:X}={:02X}", register, self.var_registers[register])
+                }
+                Operand::Index => format!("I={:03X}", self.index_register),
+            })
+            .collect();
+
+        Some(format!("{} ; {}", mnemonic, values.join(" ")))
+    }
+
+    /*
+       Runs the loaded program in lockstep with a reference trace and returns the first cycle where the PC or the
+       opcode differs from it. No keys are fed to the program so that, together with a fixed seed, the run is
+       deterministic. Input is still polled so that quitting works. Halting on an unknown opcode ends the comparison
+       early without a divergence.
+    */
+    pub fn compare_with_trace(&mut self, reference: &[TraceEntry]) -> Option<Divergence> {
+        for (cycle, &expected) in reference.iter().enumerate() {
+            self.input_driver.last_input();
+
+            let actual = match self.step(None) {
+                Ok(actual) => actual,
+                Err(err) => {
+                    println!("Halted at cycle {}: {}", cycle, err);
+                    return None;
+                }
+            };
+
+            if actual != expected {
+                return Some(Divergence {
+                    cycle,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        None
+    }
+
+    /*
+       Counts the timers down at timer_hz, driven by the number of executed cycles rather than the wall clock so the
+       countdown stays deterministic when the processor is stepped faster than real time (e.g. when comparing
+       against a trace). At the default 200Hz clock and 60Hz timer rate they tick on 60 evenly spread cycles a
+       second. Both timers tick together and independently of each other: on every tick each one that isn't 0 yet
+       is decremented, and only then is anything that depends on them checked, so a sound timer set to N plays for
+       exactly N ticks and stops on the tick it reaches 0, the same tick a delay timer set to N at the same time
+       reads 0.
+    */
+    fn advance_timers(&mut self) {
+        self.timer_phase += self.timer_hz;
+
+        while self.timer_phase >= CHIP8_CLOCK_HZ {
+            self.timer_phase -= CHIP8_CLOCK_HZ;
+
+            self.delay_timer = self.delay_timer.saturating_sub(1);
+            self.sound_timer = self.sound_timer.saturating_sub(1);
+        }
+    }
+
+    // Presents the display at the end of each frame if it changed, counting frames by cycles like the timers
+    fn advance_frame(&mut self) {
+        self.frame_phase += self.refresh_hz;
+
+        if self.frame_phase >= CHIP8_CLOCK_HZ {
+            self.frame_phase %= CHIP8_CLOCK_HZ;
+            self.vblank = true;
+            self.busy_load = self.frame_draws.min(SLOWDOWN_MAX_DRAWS);
+            self.frame_draws = 0;
+            self.display_driver.frame_ended();
+
+            if self.display_dirty || self.display_driver.needs_refresh() {
+                if self.behind && self.skipped_frames < self.max_frameskip {
+                    self.skipped_frames += 1;
+                } else {
+                    self.skipped_frames = 0;
+                    self.present_display();
+                }
+            }
+
+            if let Some(timing_stats) = &mut self.timing_stats {
+                timing_stats.frame_ended();
+            }
+        }
+    }
+
+    fn present_display(&mut self) {
+        if self.defer_clear && self.clear_pending {
+            return;
+        }
+
+        self.draw_display();
+        self.display_dirty = false;
+    }
+
+    // Draws the framebuffer, reporting the image when the display saved it to disk
+    fn draw_display(&mut self) {
+        self.display_driver.draw(&self.display);
+
+        if let Some(path) = self.display_driver.take_saved_frame() {
+            self.emit(Event::ScreenshotSaved {
+                path: &path.to_string_lossy(),
+            });
+        }
+    }
+
+    // Records that the display changed, presenting it straight away when every draw should be shown
+    fn display_changed(&mut self) {
+        self.display_dirty = true;
+        self.draw_count += 1;
+        self.frame_draws += 1;
+
+        if self.present_each_draw {
+            self.present_display();
+        }
+    }
+
+    /*
+       Runs the program under two quirk configurations, one after the other, and returns the first cycle at which
+       the machine states differ. Both runs use the same seed and feed no keys, so any difference comes from the
+       quirks alone. The processor is left in the state of the second run. Halting on an unknown opcode ends a run
+       early, and only the cycles both runs completed are compared.
+    */
+    pub fn compare_quirks(
+        &mut self,
+        program: &[u8],
+        configurations: (Quirks, Quirks),
+        seed: u64,
+        cycles: usize,
+    ) -> Option<QuirkDivergence> {
+        let mut first_run = Vec::with_capacity(cycles);
+
+        self.restart_with(program, configurations.0, seed);
+        for _ in 0..cycles {
+            self.input_driver.last_input();
+            match self.step_snapshot() {
+                Ok(snapshot) => first_run.push(snapshot),
+                Err(_) => break,
+            }
+        }
+
+        self.restart_with(program, configurations.1, seed);
+        for (cycle, first) in first_run.into_iter().enumerate() {
+            self.input_driver.last_input();
+            let Ok(second) = self.step_snapshot() else {
+                break;
+            };
+
+            if second != first {
+                return Some(QuirkDivergence {
+                    cycle,
+                    first,
+                    second,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn restart_with(&mut self, program: &[u8], quirks: Quirks, seed: u64) {
+        self.reset();
+        self.set_quirks(quirks);
+        self.set_seed(seed);
+        self.load_program(program);
+    }
+
+    // Executes an instruction and captures the machine state it left behind
+    fn step_snapshot(&mut self) -> Result<Snapshot, CpuError> {
+        let entry = self.step(None)?;
+
+        let mut hasher = DefaultHasher::new();
+        self.display.hash(&mut hasher);
+
+        Ok(Snapshot {
+            entry,
+            registers: self.var_registers,
+            index: self.index_register,
+            sp: self.sp,
+            display_hash: hasher.finish(),
+        })
+    }
+
+    /*
+       The stack and memory guards below keep a misbehaving ROM running instead of crashing the emulator: a call
+       with a full stack doesn't push its return address, a return with an empty stack does nothing and memory
+       accesses past the end wrap around. Each recovery is reported as a fault.
+    */
+    fn push_addr(&mut self, address: usize) {
+        if self.sp == self.stack.len() {
+            self.report_fault(CpuError::StackOverflow {
+                pc: self.instruction_pc,
+            });
+            return;
+        }
+
+        self.stack[self.sp] = address;
+        self.sp += 1;
+    }
+
+    fn pop_addr(&mut self) -> Option<usize> {
+        if self.sp == 0 {
+            self.report_fault(CpuError::StackUnderflow {
+                pc: self.instruction_pc,
+            });
+            return None;
+        }
+
+        self.sp -= 1;
+
+        Some(self.stack[self.sp])
+    }
+
+    fn read_ram(&mut self, address: usize) -> u8 {
+        let address = self.guard_address(address);
+
+        if self.quirks.vip_memory && VIP_DISPLAY_RAM.contains(&address) {
+            let (row, col) = vip_display_position(address);
+            return self.display[row][col..col + 8]
+                .iter()
+                .fold(0, |byte, &pixel| byte << 1 | pixel);
+        }
+
+        self.ram[address]
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        let address = self.guard_address(address);
+
+        if self.watchpoints.contains(&address) {
+            self.halt_after = Some(CpuError::Watchpoint {
+                pc: self.instruction_pc,
+                address,
+                value,
+            });
+        }
+
+        if self.quirks.vip_memory {
+            if VIP_DISPLAY_RAM.contains(&address) {
+                let (row, col) = vip_display_position(address);
+                for (i, pixel) in self.display[row][col..col + 8].iter_mut().enumerate() {
+                    *pixel = (value >> (7 - i)) & 1;
+                }
+
+                self.clear_pending = false;
+                self.display_changed();
+                return;
+            }
+
+            if VIP_INTERPRETER_RAM.contains(&address) {
+                self.report_fault(CpuError::ReservedMemoryWrite {
+                    pc: self.instruction_pc,
+                    address,
+                });
+            }
+        }
+
+        self.ram[address] = value;
+    }
+
+    fn guard_address(&mut self, address: usize) -> usize {
+        if address >= CHIP8_MEMORY {
+            self.report_fault(CpuError::MemoryOutOfRange {
+                pc: self.instruction_pc,
+                address,
+            });
+        }
+
+        address % CHIP8_MEMORY
+    }
+
+    // Reports a fault the processor recovered from, once per instruction address so a looping ROM doesn't flood
+    fn report_fault(&mut self, fault: CpuError) {
+        if !self.reported_faults.insert(self.instruction_pc) {
+            return;
+        }
+
+        println!("Recovered from fault: {}", fault);
+
+        let message = fault.to_string();
+        if self.fault_overlay {
+            self.display_driver.show_message(&message);
+        }
+
+        self.emit(Event::Fault {
+            pc: self.instruction_pc,
+            message: &message,
+        });
+    }
+
+    // Fetches instruction, which is 2 successive bytes in memory. Increments the program counter by 2 (to be ready for next instruction)
+    fn get_instruction(&mut self) -> u16 {
+        let instruction: u16 =
+            (self.read_ram(self.pc) as u16) << 8 | self.read_ram(self.pc + 1) as u16;
+
+        self.pc += 2;
+        return instruction;
+    }
+
+    // Whether the program is stuck on FX0A until a key is fed to step, so embedders know to poll their input
+    pub fn waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // Reads the instruction at PC without advancing it, 0 if PC doesn't point at a whole instruction in memory
+    pub fn peek_instruction(&self) -> u16 {
+        match self.ram.get(self.pc..self.pc + 2) {
+            Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+            None => 0,
         }
     }
+
+    fn decode_and_execute_instruction(
+        &mut self,
+        instruction: u16,
+        keycode: Option<u8>,
+    ) -> Result<(), CpuError> {
+        let nibbles: (u16, u16, u16, u8) = (
+            (instruction & 0xF000) >> 12 as u8,
+            (instruction & 0x0F00) >> 8 as u8,
+            (instruction & 0x00F0) >> 4 as u8,
+            (instruction & 0x000F) as u8,
+        );
+
+        let nnn = (instruction & 0x0FFF) as usize; // Last 3 nibbles
+        let nn: u8 = (instruction & 0x00FF) as u8; // Last byte
+        let x: usize = nibbles.1 as usize; // Second nibble
+        let y: usize = nibbles.2 as usize; // Third nibble
+        let n: usize = nibbles.3 as usize; // Fourth nibble
+
+        match nibbles {
+            (0x00, 0x00, 0x0e, 0x00) => {
+                // Clear screen
+                self.instruction_clear_screen();
+            }
+            (0x00, 0x00, 0x0e, 0x0e) => {
+                // Return from subroutine
+                self.instruction_return();
+            }
+            (0x06, _, _, _) => {
+                // Set variable address value
+                self.instruction_set(x, nn);
+            }
+            (0x07, _, _, _) => {
+                // Add to variable address
+                self.instruction_add(x, nn);
+            }
+            (0x01, _, _, _) => {
+                // Jump to address
+                self.instruction_jmp(nnn);
+            }
+            (0x02, _, _, _) => {
+                // Call Subroutine
+                self.instruction_call_subroutine(nnn);
+            }
+            (0x0A, _, _, _) => {
+                // Set index register
+                self.instruction_set_index(nnn);
+            }
+            (0x0F, _, 0x01, 0x0E) => self.instruction_add_index(x),
+            (0x0B, _, _, _) => self.instruction_jump_with_offset(x, nnn),
+            (0x0C, _, _, _) => self.instruction_random(x, nn),
+            (0x0D, _, _, _) => {
+                // Display and Draw
+                self.instruction_draw_display(x, y, n);
+            }
+            (0x03, _, _, _) => {
+                // Skip one instruction if VX == NN
+                self.instruction_skip_equal(x, nn)
+            }
+            (0x0E, _, 0x09, 0x0E) => {
+                self.instruction_skip_key(x);
+            }
+            (0x0E, _, 0x0A, 0x01) => {
+                self.instruction_skip_not_key(x);
+            }
+            (0x0F, _, 0x00, 0x0A) => self.instruction_get_key(x, keycode),
+            (0x04, _, _, _) => self.instruction_skip_not_equal(x, nn),
+            (0x05, _, _, 0x00) => self.instruction_skip_register_equal(x, y),
+            (0x09, _, _, 0x00) => self.instruction_skip_register_not_equal(x, y),
+            (0x08, _, _, 0x00) => self.instruction_alu_set(x, y),
+            (0x08, _, _, 0x01) => self.instruction_alu_or(x, y),
+            (0x08, _, _, 0x02) => self.instruction_alu_and(x, y),
+            (0x08, _, _, 0x03) => self.instruction_alu_xor(x, y),
+            (0x08, _, _, 0x04) => self.instruction_alu_add(x, y),
+            (0x08, _, _, 0x05) => self.instruction_alu_subtract(x, x, y),
+            (0x08, _, _, 0x07) => self.instruction_alu_subtract(x, y, x),
+            (0x08, _, _, 0x06) => self.instruction_alu_shift(x, y, false),
+            (0x08, _, _, 0x0E) => self.instruction_alu_shift(x, y, true),
+            (0x0F, _, 0x02, 0x09) => self.instruction_font_character(x),
+            (0x0F, _, 0x03, 0x03) => self.instruction_bcd_convert(x),
+            (0x0F, _, 0x05, 0x05) => self.instruction_store_registers(x),
+            (0x0F, _, 0x06, 0x05) => self.instruction_load_registers(x),
+            (0x0F, _, 0x00, 0x07) => self.instruction_get_delay_timer(x),
+            (0x0F, _, 0x01, 0x05) => self.instruction_set_delay_timer(x),
+            (0x0F, _, 0x01, 0x08) => self.instruction_set_sound_timer(x),
+
+            _ => {
+                return Err(CpuError::UnknownOpcode {
+                    pc: self.pc - 2,
+                    opcode: instruction,
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instruction_jmp(&mut self, address: usize) {
+        self.pc = address;
+    }
+
+    fn instruction_jump_with_offset(&mut self, vx_register: usize, address: usize) {
+        // Ambiguous instruction -- CHIP-48/SUPER-CHIP treat BXNN as a jump to XNN offset by VX
+        let offset_register = if self.quirks.jump_uses_vx {
+            vx_register
+        } else {
+            0x00
+        };
+
+        self.instruction_jmp(address + self.var_registers[offset_register] as usize);
+    }
+
+    fn instruction_clear_screen(&mut self) {
+        for i in 0..self.display.len() {
+            for j in 0..self.display[i].len() {
+                self.display[i][j] = 0;
+            }
+        }
+
+        self.clear_pending = true;
+        self.display_changed();
+    }
+
+    /*
+       XORs an N row sprite read from I onto the display at (VX, VY). VF is written on every draw: 1 if any set
+       pixel was erased (a collision), otherwise exactly 0. VX and VY are read before VF is written so a coordinate
+       held in VF is still honored. The collision is collected while drawing and VF is written once at the end.
+    */
+    fn instruction_draw_display(&mut self, vx: usize, vy: usize, height: usize) {
+        if self.quirks.display_wait {
+            // Re-execute this instruction until the next vertical blank, like FX0A does while waiting for a key
+            if !self.vblank {
+                self.pc -= 2;
+                return;
+            }
+
+            self.vblank = false;
+        }
+
+        if height == 0 {
+            let fault = CpuError::EmptySprite {
+                pc: self.instruction_pc,
+            };
+
+            match self.empty_sprite_policy {
+                EmptySpritePolicy::Ignore => {}
+                EmptySpritePolicy::Warn => self.report_fault(fault),
+                EmptySpritePolicy::Error => self.halt_after = Some(fault),
+            }
+        }
+
+        // The starting coordinate always wraps, only the pixels of the sprite itself are subject to clipping
+        let row = self.var_registers[vy] as usize % 32;
+        let col = self.var_registers[vx] as usize % 64;
+
+        let mut collision = 0;
+
+        for i in 0..height {
+            if self.quirks.clip_sprites && row + i >= 32 {
+                break;
+            }
+
+            let sprite_row = self.read_ram(self.index_register + i);
+
+            // For each bit in the orw
+            for j in 0..8 {
+                if self.quirks.clip_sprites && col + 7 - j >= 64 {
+                    continue;
+                }
+
+                let bit = (sprite_row >> j) & 1;
+
+                let pixel_screen = self.display[(row + i) % 32][(col + 7 - j) % 64];
+
+                if bit == 1 && pixel_screen == 1 {
+                    // We're going to unset a pixel, so set flag in VF
+                    collision = 1;
+                }
+
+                self.display[(row + i) % 32][(col + 7 - j) % 64] ^= bit;
+            }
+        }
+
+        self.set_flag(collision);
+        self.clear_pending = false;
+
+        self.display_changed();
+    }
+
+    /*
+       Writes a flag result to VF, counting the writes so that step can check each instruction wrote as many flags
+       as it should. Instructions that also store a result write it first, so that with VF as the destination the
+       flag is what's left in VF.
+    */
+    fn set_flag(&mut self, value: u8) {
+        self.var_registers[CHIP8_VF_INDEX] = value;
+        self.vf_writes = self.vf_writes.wrapping_add(1);
+    }
+
+    fn instruction_call_subroutine(&mut self, address: usize) {
+        self.push_addr(self.pc);
+        self.pc = address;
+    }
+
+    fn instruction_return(&mut self) {
+        if let Some(ret_address) = self.pop_addr() {
+            self.pc = ret_address;
+        }
+    }
+
+    fn instruction_set(&mut self, register: usize, value: u8) {
+        self.var_registers[register] = value;
+    }
+
+    fn instruction_add(&mut self, register: usize, value: u8) {
+        // ADD WITHOUT CARRY FLAG
+
+        let (value, _) = self.var_registers[register].overflowing_add(value);
+        self.var_registers[register] = value;
+    }
+
+    fn instruction_set_index(&mut self, value: usize) {
+        self.index_register = value;
+    }
+
+    fn instruction_add_index(&mut self, register: usize) {
+        let mut index_value = self.index_register;
+
+        index_value += self.var_registers[register] as usize;
+
+        self.index_register = index_value;
+
+        if index_value >= 0x100 {
+            // For compatibility -- (kind of) overflow but not really
+            self.set_flag(1);
+        }
+    }
+
+    fn instruction_skip_equal(&mut self, register: usize, value: u8) {
+        if self.var_registers[register] == value {
+            self.pc += 2
+        }
+    }
+
+    fn instruction_skip_not_equal(&mut self, register: usize, value: u8) {
+        if self.var_registers[register] != value {
+            self.pc += 2
+        }
+    }
+
+    fn instruction_skip_key(&mut self, register: usize) {
+        if self.input_driver.is_pressed(self.var_registers[register]) {
+            self.pc += 2
+        }
+    }
+
+    fn instruction_skip_not_key(&mut self, register: usize) {
+        if !self.input_driver.is_pressed(self.var_registers[register]) {
+            self.pc += 2
+        }
+    }
+
+    /*
+       Blocks until a key is pressed by executing again until it gets one. start() polls the input once per step, so
+       every attempt sees a fresh poll rather than spinning on a stale one. While waiting the machine idles: the
+       timers count down and frames end as time passes, but nothing else changes.
+    */
+    fn instruction_get_key(&mut self, register: usize, keycode: Option<u8>) {
+        self.waiting_for_key = keycode.is_none();
+
+        if keycode.is_none() {
+            self.pc -= 2;
+            return;
+        }
+
+        self.var_registers[register] = keycode.unwrap();
+    }
+
+    fn instruction_skip_register_equal(&mut self, vx_register: usize, vy_register: usize) {
+        if self.var_registers[vx_register] == self.var_registers[vy_register] {
+            self.pc += 2;
+        }
+    }
+
+    fn instruction_skip_register_not_equal(&mut self, vx_register: usize, vy_register: usize) {
+        if self.var_registers[vx_register] != self.var_registers[vy_register] {
+            self.pc += 2;
+        }
+    }
+
+    fn instruction_random(&mut self, vx_register: usize, value: u8) {
+        // Randomly generates a number, ANDs it with value, and stores it in vx register
+        let random_value = self.rng.gen::<u8>();
+
+        self.var_registers[vx_register] = random_value & value
+    }
+
+    fn instruction_alu_set(&mut self, vx_register: usize, vy_register: usize) {
+        // Set value of vx register to value of vy register
+        self.var_registers[vx_register] = self.var_registers[vy_register]
+    }
+
+    fn instruction_alu_or(&mut self, vx_register: usize, vy_register: usize) {
+        // Binary OR
+        // Or vx register value with vy register value and store in vx register
+        self.var_registers[vx_register] |= self.var_registers[vy_register]
+    }
+
+    fn instruction_alu_and(&mut self, vx_register: usize, vy_register: usize) {
+        // Binary AND
+        // And vx register value with vy register value and store in vx register
+        self.var_registers[vx_register] &= self.var_registers[vy_register]
+    }
+
+    fn instruction_alu_xor(&mut self, vx_register: usize, vy_register: usize) {
+        // Logical XOR
+        // Xor vx register value with vy register value and store in vx register
+        self.var_registers[vx_register] ^= self.var_registers[vy_register]
+    }
+
+    fn instruction_alu_add(&mut self, vx_register: usize, vy_register: usize) {
+        // Add vx register value with vy register value and store in vx register
+        // if overflow occurred, set VF to 1, else set it to 0
+        let (value, overflow) =
+            self.var_registers[vx_register].overflowing_add(self.var_registers[vy_register]);
+
+        self.var_registers[vx_register] = value;
+        self.set_flag(overflow as u8);
+    }
+
+    /*
+       Stores minuend - subtrahend in VX: VX - VY for 8XY5 and VY - VX for 8XY7. VF is 1 when there was no borrow,
+       i.e. when the minuend was at least as large as the subtrahend, which includes them being equal.
+    */
+    fn instruction_alu_subtract(&mut self, vx_register: usize, minuend: usize, subtrahend: usize) {
+        let (value, borrow) =
+            self.var_registers[minuend].overflowing_sub(self.var_registers[subtrahend]);
+
+        self.var_registers[vx_register] = value;
+        self.set_flag(!borrow as u8);
+    }
+
+    fn instruction_alu_shift(&mut self, vx_register: usize, vy_register: usize, left_shift: bool) {
+        if self.quirks.shift_uses_vy {
+            self.var_registers[vx_register] = self.var_registers[vy_register];
+        }
+
+        let mut vx_value = self.var_registers[vx_register];
+
+        let vf_value: u8;
+
+        if left_shift {
+            // left shift, VF is the bit shifted out of the top
+            vf_value = vx_value >> 7;
+            vx_value <<= 1;
+        } else {
+            // right shift
+            vf_value = vx_value & 0x01;
+            vx_value >>= 1;
+        }
+
+        self.var_registers[vx_register] = vx_value;
+        self.set_flag(vf_value);
+    }
+
+    // Points I at the small font glyph for the low nibble of VX, so values above 0xF don't run past the font
+    fn instruction_font_character(&mut self, vx_register: usize) {
+        self.index_register = (self.var_registers[vx_register] & 0x0F) as usize * 5
+    }
+
+    /*
+       Stores the decimal digits of VX at I, I + 1 and I + 2, hundreds first, e.g. 0 as 0, 0, 0 and 255 as 2, 5, 5.
+       I itself is left unchanged, unlike FX55 and FX65 on the original interpreter.
+    */
+    fn instruction_bcd_convert(&mut self, vx_register: usize) {
+        let mut vx_value = self.var_registers[vx_register];
+
+        for i in (0..3).rev() {
+            let digit = vx_value % 10;
+            self.write_ram(self.index_register + i, digit);
+            vx_value /= 10;
+        }
+    }
+
+    /*
+       FX55 stores V0 to VX in memory starting at I, FX65 loads them back from there. They go through the same memory
+       accesses as every other instruction, so with the VIP memory quirk they read and draw display RAM. The quirk
+       also leaves I pointing just past the last register stored or loaded, as the VIP interpreter did; otherwise I
+       is unchanged like on SUPER-CHIP.
+    */
+    fn instruction_store_registers(&mut self, vx_register: usize) {
+        for i in 0..=vx_register {
+            self.write_ram(self.index_register + i, self.var_registers[i]);
+        }
+
+        if self.quirks.vip_memory {
+            self.index_register += vx_register + 1;
+        }
+    }
+
+    fn instruction_load_registers(&mut self, vx_register: usize) {
+        for i in 0..=vx_register {
+            self.var_registers[i] = self.read_ram(self.index_register + i);
+        }
+
+        if self.quirks.vip_memory {
+            self.index_register += vx_register + 1;
+        }
+    }
+
+    fn instruction_get_delay_timer(&mut self, vx_register: usize) {
+        self.var_registers[vx_register] = self.delay_timer
+    }
+
+    fn instruction_set_delay_timer(&mut self, vx_register: usize) {
+        self.delay_timer = self.var_registers[vx_register]
+    }
+
+    fn instruction_set_sound_timer(&mut self, vx_register: usize) {
+        self.sound_timer = self.var_registers[vx_register]
+    }
+}
+
+/*
+    How many flags an instruction writes to VF, for the self-check in step: the ALU instructions producing a carry,
+    borrow or shifted out bit and DXYN exactly one, FX1E one only when I passes 0xFF and all others none. A draw
+    that is retried until the vertical blank writes its flag once it's finally executed.
+*/
+fn expected_flag_writes(instruction: u16, retried: bool) -> RangeInclusive<u32> {
+    match instruction & 0xF00F {
+        0x8004..=0x8007 | 0x800E => 1..=1,
+        0xD000..=0xD00F if !retried => 1..=1,
+        0xF00E if instruction & 0x00F0 == 0x0010 => 0..=1,
+        _ => 0..=0,
+    }
+}
+
+// The display row and first column a byte of the VIP's display RAM maps to, 8 pixels per byte with the MSB leftmost
+fn vip_display_position(address: usize) -> (usize, usize) {
+    let offset = address - VIP_DISPLAY_RAM.start;
+
+    (offset / 8, offset % 8 * 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use super::*;
+    use crate::drivers::{NullDisplay, NullInput};
+    use crate::frames::{assert_frames_eq, from_ascii, Frame};
+    use crate::quirks::Variant;
+
+    // The font glyph for 0, which the tests draw since it's at address 0
+    #[rustfmt::skip]
+    const GLYPH_0: [&str; 5] = [
+        "####",
+        "#..#",
+        "#..#",
+        "#..#",
+        "####",
+    ];
+
+    // A headless processor with the opcodes loaded at the program origin
+    fn processor(ops: &[u16]) -> Processor {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        let program: Vec<u8> = ops.iter().flat_map(|op| op.to_be_bytes()).collect();
+        processor.load_program(&program);
+        processor
+    }
+
+    // Keeps every frame presented, shared with the test since the processor owns the backend
+    #[derive(Clone, Default)]
+    struct RecordingDisplay {
+        frames: Rc<RefCell<Vec<Frame>>>,
+    }
+
+    impl DisplayBackend for RecordingDisplay {
+        fn draw(&mut self, pixels: &Frame) {
+            self.frames.borrow_mut().push(*pixels);
+        }
+    }
+
+    // The processor of processor(), drawing to a recording display
+    fn recorded_processor(ops: &[u16]) -> (Processor, Rc<RefCell<Vec<Frame>>>) {
+        let display = RecordingDisplay::default();
+        let frames = Rc::clone(&display.frames);
+
+        let mut processor = Processor::new(display, NullInput);
+        let program: Vec<u8> = ops.iter().flat_map(|op| op.to_be_bytes()).collect();
+        processor.load_program(&program);
+        (processor, frames)
+    }
+
+    fn steps(processor: &mut Processor, count: usize) {
+        for _ in 0..count {
+            processor.step(None).unwrap();
+        }
+    }
+
+    #[test]
+    fn run_opcodes_executes_the_opcodes_in_order() {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        processor.run_opcodes(&[0x6005, 0x6103, 0x8014]);
+
+        assert_eq!(processor.registers()[0], 8);
+        assert_eq!(processor.registers()[1], 3);
+        assert_eq!(processor.pc(), 0x206);
+        assert_eq!(processor.instructions(), 3);
+    }
+
+    #[test]
+    fn run_opcodes_stops_a_jump_to_self_at_the_cap() {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        processor.run_opcodes(&[0x6001, 0x1202]);
+
+        assert_eq!(processor.instructions(), RUN_OPCODES_MAX_CYCLES as u64);
+        assert_eq!(processor.pc(), 0x202);
+    }
+
+    #[test]
+    fn timers_tick_60_times_a_second_by_default() {
+        let mut processor = processor(&[0x1200]);
+        processor.delay_timer = 0xFF;
+        processor.sound_timer = 0xFF;
+
+        steps(&mut processor, CHIP8_CLOCK_HZ as usize);
+
+        assert_eq!(processor.delay_timer(), 0xFF - 60);
+        assert_eq!(processor.sound_timer(), 0xFF - 60);
+    }
+
+    #[test]
+    fn timers_tick_at_a_non_default_rate() {
+        // At 100Hz the timers tick on every other cycle of the 200Hz clock
+        let mut processor = processor(&[0x1200]);
+        processor.set_timer_hz(100);
+        processor.delay_timer = 10;
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.delay_timer(), 10);
+        steps(&mut processor, 1);
+        assert_eq!(processor.delay_timer(), 9);
+        steps(&mut processor, 17);
+        assert_eq!(processor.delay_timer(), 1);
+        steps(&mut processor, 1);
+        assert_eq!(processor.delay_timer(), 0);
+
+        processor.delay_timer = 0xFF;
+        steps(&mut processor, CHIP8_CLOCK_HZ as usize);
+        assert_eq!(processor.delay_timer(), 0xFF - 100);
+    }
+
+    #[test]
+    fn toggle_pixel_flips_the_pixel_and_ignores_pixels_off_the_display() {
+        let mut processor = processor(&[]);
+
+        processor.toggle_pixel(63, 31);
+        assert_eq!(processor.framebuffer()[31][63], 1);
+        processor.toggle_pixel(63, 31);
+        assert_eq!(processor.framebuffer()[31][63], 0);
+
+        processor.toggle_pixel(64, 0);
+        processor.toggle_pixel(0, 32);
+        processor.toggle_pixel(usize::MAX, usize::MAX);
+        assert_eq!(processor.framebuffer(), &[[0; 64]; 32]);
+    }
+
+    #[test]
+    fn draw_without_a_collision_clears_vf() {
+        // VF = 1, I = glyph 0, draw it at (V0, V0) = (0, 0)
+        let mut processor = processor(&[0x6F01, 0xA000, 0xD005]);
+        steps(&mut processor, 3);
+
+        assert_eq!(processor.registers()[0xF], 0);
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&GLYPH_0, (0, 0)));
+    }
+
+    #[test]
+    fn draw_erasing_a_pixel_sets_vf() {
+        // Drawing the same sprite twice in the same place erases it
+        let mut processor = processor(&[0xA000, 0xD005, 0xD005]);
+
+        steps(&mut processor, 2);
+        assert_eq!(processor.registers()[0xF], 0);
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.registers()[0xF], 1);
+        assert_eq!(processor.framebuffer(), &[[0; 64]; 32]);
+    }
+
+    #[test]
+    fn draw_with_a_single_overlapping_pixel_sets_vf() {
+        // Glyph 0 at (0, 0) and again at (3, 0), where only its top right and bottom right corners overlap
+        let mut processor = processor(&[0xA000, 0xD005, 0x6003, 0xD015]);
+        steps(&mut processor, 4);
+
+        assert_eq!(processor.registers()[0xF], 1);
+        assert_eq!(processor.framebuffer()[0][..8], [1, 1, 1, 0, 1, 1, 1, 0]);
+        assert_eq!(processor.framebuffer()[1][..8], [1, 0, 0, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn draw_uses_vf_as_a_coordinate_before_writing_the_flag() {
+        // VF = 10, draw glyph 0 at (VF, VF)
+        let mut processor = processor(&[0x6F0A, 0xA000, 0xDFF5]);
+        steps(&mut processor, 3);
+
+        assert_eq!(processor.registers()[0xF], 0);
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&GLYPH_0, (10, 10)));
+    }
+
+    #[test]
+    fn set_index_accepts_any_address_in_memory() {
+        let mut processor = processor(&[]);
+        processor.set_index_policy(IndexPolicy::Error);
+
+        assert_eq!(processor.set_index(0xFFF), Ok(()));
+        assert_eq!(processor.index(), 0xFFF);
+    }
+
+    #[test]
+    fn set_index_wraps_out_of_range_values_by_default() {
+        let mut processor = processor(&[]);
+
+        assert_eq!(processor.set_index(0x1234), Ok(()));
+        assert_eq!(processor.index(), 0x234);
+    }
+
+    #[test]
+    fn set_index_clamps_out_of_range_values_to_the_last_address() {
+        let mut processor = processor(&[]);
+        processor.set_index_policy(IndexPolicy::Clamp);
+
+        assert_eq!(processor.set_index(0x1234), Ok(()));
+        assert_eq!(processor.index(), 0xFFF);
+    }
+
+    #[test]
+    fn set_index_rejects_out_of_range_values_leaving_i_unchanged() {
+        let mut processor = processor(&[]);
+        processor.set_index_policy(IndexPolicy::Error);
+        processor.set_index(0x300).unwrap();
+
+        assert_eq!(
+            processor.set_index(0x1000),
+            Err(CpuError::IndexOutOfRange { value: 0x1000 })
+        );
+        assert_eq!(processor.index(), 0x300);
+    }
+
+    #[test]
+    fn peek_instruction_leaves_pc_unchanged() {
+        let mut processor = processor(&[0x6005, 0x6103]);
+
+        assert_eq!(processor.peek_instruction(), 0x6005);
+        assert_eq!(processor.peek_instruction(), 0x6005);
+        assert_eq!(processor.pc(), 0x200);
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.peek_instruction(), 0x6103);
+        assert_eq!(processor.pc(), 0x202);
+    }
+
+    #[test]
+    fn peek_instruction_is_0_without_a_whole_instruction_at_pc() {
+        let mut processor = processor(&[]);
+        processor.ram[0xFFF] = 0x12;
+
+        processor.pc = 0xFFF;
+        assert_eq!(processor.peek_instruction(), 0);
+        processor.pc = CHIP8_MEMORY;
+        assert_eq!(processor.peek_instruction(), 0);
+    }
+
+    #[test]
+    fn draw_wraps_a_start_beyond_the_display() {
+        // Draw glyph 0 at (V0, V1) = (70, 40), which wraps to (6, 8)
+        let mut processor = processor(&[0x6046, 0x6128, 0xA000, 0xD015]);
+        steps(&mut processor, 4);
+
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&GLYPH_0, (6, 8)));
+    }
+
+    #[test]
+    fn draw_wraps_sprites_past_the_edges_by_default() {
+        // Glyph 0 at (62, 30) continues on the left and at the top of the display
+        let mut processor = processor(&[0x603E, 0x611E, 0xA000, 0xD015]);
+        steps(&mut processor, 4);
+
+        let mut expected = [[0; 64]; 32];
+        for (row, line) in GLYPH_0.iter().enumerate() {
+            for (col, art) in line.bytes().enumerate() {
+                expected[(30 + row) % 32][(62 + col) % 64] = (art == b'#') as u8;
+            }
+        }
+        assert_frames_eq(processor.framebuffer(), &expected);
+    }
+
+    #[test]
+    fn draw_clips_sprites_past_the_edges_with_the_clip_quirk() {
+        // Glyph 0 at (V0, V1) = (126, 62), whose start wraps to (62, 30) before the rest of it is clipped
+        let mut processor = processor(&[0x607E, 0x613E, 0xA000, 0xD015]);
+        processor.set_quirks(Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        });
+        steps(&mut processor, 4);
+
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&GLYPH_0, (62, 30)));
+    }
+
+    // 5XY1 isn't an instruction in any variant this emulator supports
+    const UNKNOWN_OPCODE: u16 = 0x5121;
+
+    #[test]
+    fn unknown_opcodes_are_skipped_by_default() {
+        let mut processor = processor(&[UNKNOWN_OPCODE, 0x6005]);
+
+        assert_eq!(
+            processor.step(None),
+            Ok(TraceEntry {
+                pc: 0x200,
+                opcode: UNKNOWN_OPCODE
+            })
+        );
+        steps(&mut processor, 1);
+
+        assert_eq!(processor.registers()[0], 5);
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    #[test]
+    fn unknown_opcodes_halt_in_front_of_them_with_the_halt_policy() {
+        let mut processor = processor(&[UNKNOWN_OPCODE]);
+        processor.set_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+
+        for _ in 0..2 {
+            assert_eq!(
+                processor.step(None),
+                Err(CpuError::UnknownOpcode {
+                    pc: 0x200,
+                    opcode: UNKNOWN_OPCODE
+                })
+            );
+            assert_eq!(processor.pc(), 0x200);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "0x5121 at 0x200 is not supported")]
+    fn unknown_opcodes_panic_with_the_panic_policy() {
+        let mut processor = processor(&[UNKNOWN_OPCODE]);
+        processor.set_unknown_opcode_policy(UnknownOpcodePolicy::Panic);
+
+        let _ = processor.step(None);
+    }
+
+    // Runs FX33 on V0 holding the value with I at 0x300, returning the three digits stored
+    fn bcd(value: u8) -> (Processor, [u8; 3]) {
+        let mut processor = processor(&[0x6000 | value as u16, 0xA300, 0xF033]);
+        steps(&mut processor, 3);
+
+        let mut digits = [0; 3];
+        digits.copy_from_slice(&processor.memory()[0x300..0x303]);
+        (processor, digits)
+    }
+
+    #[test]
+    fn bcd_stores_hundreds_tens_and_ones() {
+        assert_eq!(bcd(0).1, [0, 0, 0]);
+        assert_eq!(bcd(9).1, [0, 0, 9]);
+        assert_eq!(bcd(137).1, [1, 3, 7]);
+        assert_eq!(bcd(255).1, [2, 5, 5]);
+    }
+
+    #[test]
+    fn bcd_leaves_i_unchanged() {
+        let (processor, _) = bcd(255);
+
+        assert_eq!(processor.index(), 0x300);
+    }
+
+    #[test]
+    fn draws_take_draw_cost_cycles_of_timer_time() {
+        // At 200Hz the timers tick on every cycle, so a costly draw ticks them several times
+        let mut processor = processor(&[0xA000, 0xD005, 0x6001]);
+        processor.set_draw_cost(4);
+        processor.set_timer_hz(CHIP8_CLOCK_HZ);
+        processor.delay_timer = 10;
+
+        steps(&mut processor, 1);
+        assert_eq!((processor.cycles(), processor.delay_timer()), (1, 9));
+        steps(&mut processor, 1);
+        assert_eq!((processor.cycles(), processor.delay_timer()), (5, 5));
+        steps(&mut processor, 1);
+        assert_eq!((processor.cycles(), processor.delay_timer()), (6, 4));
+        assert_eq!(processor.instructions(), 3);
+    }
+
+    #[test]
+    fn draws_waiting_for_the_vertical_blank_cost_a_cycle_per_attempt() {
+        let mut processor = processor(&[0xA000, 0xD005]);
+        processor.set_draw_cost(4);
+        processor.set_quirks(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+
+        // A frame is 200 / 60 cycles, so the first vertical blank comes at the end of the fourth cycle
+        steps(&mut processor, 4);
+        assert_eq!((processor.cycles(), processor.pc()), (4, 0x202));
+        steps(&mut processor, 1);
+        assert_eq!((processor.cycles(), processor.pc()), (8, 0x204));
+    }
+
+    // Runs the ALU instruction with VX and VY holding the values and VF the opposite of the flag it should produce
+    fn alu(instruction: u16, vx: u8, vy: u8, flag: u8) -> Processor {
+        let x = (instruction >> 8 & 0xF) as usize;
+        let y = (instruction >> 4 & 0xF) as usize;
+
+        let mut processor = processor(&[instruction]);
+        processor.var_registers[CHIP8_VF_INDEX] = flag ^ 1;
+        processor.var_registers[y] = vy;
+        processor.var_registers[x] = vx;
+        steps(&mut processor, 1);
+
+        assert_eq!(processor.vf_writes, 1, "{:04X} wrote VF once", instruction);
+        processor
+    }
+
+    #[test]
+    fn add_sets_vf_to_the_carry() {
+        assert_eq!(alu(0x8014, 0xFF, 0x02, 1).registers()[..2], [0x01, 0x02]);
+        assert_eq!(alu(0x8014, 0xFF, 0x02, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8014, 0x01, 0x02, 0).registers()[0], 0x03);
+        assert_eq!(alu(0x8014, 0x01, 0x02, 0).registers()[0xF], 0);
+    }
+
+    #[test]
+    fn subtract_sets_vf_unless_it_borrows() {
+        assert_eq!(alu(0x8015, 0x05, 0x03, 1).registers()[..2], [0x02, 0x03]);
+        assert_eq!(alu(0x8015, 0x05, 0x03, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8015, 0x03, 0x05, 0).registers()[0], 0xFE);
+        assert_eq!(alu(0x8015, 0x03, 0x05, 0).registers()[0xF], 0);
+
+        // Equal values don't borrow, so the flag is set rather than left as it was
+        assert_eq!(alu(0x8015, 0x04, 0x04, 1).registers()[0], 0);
+        assert_eq!(alu(0x8015, 0x04, 0x04, 1).registers()[0xF], 1);
+    }
+
+    #[test]
+    fn subtract_reversed_stores_vy_minus_vx_in_vx() {
+        assert_eq!(alu(0x8017, 0x03, 0x05, 1).registers()[..2], [0x02, 0x05]);
+        assert_eq!(alu(0x8017, 0x03, 0x05, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8017, 0x05, 0x03, 0).registers()[0], 0xFE);
+        assert_eq!(alu(0x8017, 0x05, 0x03, 0).registers()[0xF], 0);
+    }
+
+    #[test]
+    fn shifts_set_vf_to_the_bit_shifted_out() {
+        assert_eq!(alu(0x8006, 0x03, 0x03, 1).registers()[0], 0x01);
+        assert_eq!(alu(0x8006, 0x03, 0x03, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8006, 0x02, 0x02, 0).registers()[0xF], 0);
+
+        // The flag is 1, not the 0x80 bit itself
+        assert_eq!(alu(0x800E, 0x81, 0x81, 1).registers()[0], 0x02);
+        assert_eq!(alu(0x800E, 0x81, 0x81, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x800E, 0x7F, 0x7F, 0).registers()[0xF], 0);
+    }
+
+    #[test]
+    fn flag_replaces_the_result_when_vf_is_the_destination() {
+        assert_eq!(alu(0x8F14, 0xFF, 0x02, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8F15, 0x05, 0x03, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8F15, 0x03, 0x05, 0).registers()[0xF], 0);
+        assert_eq!(alu(0x8F17, 0x03, 0x05, 1).registers()[0xF], 1);
+        assert_eq!(alu(0x8F06, 0x02, 0x02, 0).registers()[0xF], 0);
+        assert_eq!(alu(0x8F0E, 0x81, 0x81, 1).registers()[0xF], 1);
+    }
+
+    #[test]
+    fn stored_registers_load_back_leaving_i_unchanged() {
+        // V0 to V3 = 1 to 4, store them at 0x300, clear V0 to V3 and load them back
+        let mut processor = processor(&[
+            0x6001, 0x6102, 0x6203, 0x6304, 0xA300, 0xF355, 0x6000, 0x6100, 0x6200, 0x6300, 0xF365,
+        ]);
+
+        steps(&mut processor, 6);
+        assert_eq!(processor.memory()[0x300..0x305], [1, 2, 3, 4, 0]);
+        assert_eq!(processor.index(), 0x300);
+
+        steps(&mut processor, 5);
+        assert_eq!(processor.registers()[..5], [1, 2, 3, 4, 0]);
+        assert_eq!(processor.index(), 0x300);
+    }
+
+    #[test]
+    fn vip_memory_quirk_advances_i_past_the_registers() {
+        let mut processor = processor(&[0xA300, 0xF355, 0xF265]);
+        processor.set_quirks(Quirks {
+            vip_memory: true,
+            ..Quirks::default()
+        });
+
+        steps(&mut processor, 2);
+        assert_eq!(processor.index(), 0x304);
+        steps(&mut processor, 1);
+        assert_eq!(processor.index(), 0x307);
+    }
+
+    #[test]
+    fn vip_memory_quirk_maps_stored_registers_to_the_display() {
+        // Store 0xFF and 0x81 to the first two bytes of display RAM, then load the first one back into V2
+        let mut processor = processor(&[0x60FF, 0x6181, 0xAF00, 0xF155, 0xAF00, 0xF265]);
+        processor.set_quirks(Quirks {
+            vip_memory: true,
+            ..Quirks::default()
+        });
+        steps(&mut processor, 4);
+
+        let mut expected = [[0; 64]; 32];
+        expected[0][..16].copy_from_slice(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1]);
+        assert_frames_eq(processor.framebuffer(), &expected);
+
+        steps(&mut processor, 2);
+        assert_eq!(processor.registers()[..3], [0xFF, 0x81, 0x00]);
+    }
+
+    #[test]
+    fn pause_after_pauses_at_exactly_n_instructions() {
+        // Counts up in V0 forever
+        let mut processor = processor(&[0x7001, 0x1200]);
+        processor.set_pause_after(5);
+
+        for _ in 0..10 {
+            processor.tick();
+        }
+        assert!(processor.paused);
+        assert_eq!(processor.instructions(), 5);
+        assert_eq!(processor.registers()[0], 3);
+
+        // It stays paused for inspection and only pauses once, carrying on after a resume
+        processor.paused = false;
+        for _ in 0..10 {
+            processor.tick();
+        }
+        assert!(!processor.paused);
+        assert_eq!(processor.instructions(), 15);
+    }
+
+    #[test]
+    fn watchpoint_halts_after_the_write_with_the_pc_address_and_value() {
+        // FX33 of 123 writes the tens digit to the watched 0x301
+        let mut processor = processor(&[0x607B, 0xA300, 0xF033, 0x6101]);
+        processor.add_watchpoint(0x301);
+        steps(&mut processor, 2);
+
+        assert_eq!(
+            processor.step(None).unwrap_err(),
+            CpuError::Watchpoint {
+                pc: 0x204,
+                address: 0x301,
+                value: 2,
+            }
+        );
+        // The write and the rest of the instruction still happened, and execution carries on after it
+        assert_eq!(processor.memory()[0x300..0x303], [1, 2, 3]);
+        assert_eq!(processor.pc(), 0x206);
+        steps(&mut processor, 1);
+        assert_eq!(processor.registers()[1], 1);
+    }
+
+    #[test]
+    fn watchpoint_ignores_writes_elsewhere() {
+        let mut processor = processor(&[0x607B, 0xA300, 0xF033]);
+        processor.add_watchpoint(0x303);
+
+        steps(&mut processor, 3);
+    }
+
+    #[test]
+    fn font_character_points_at_each_small_glyph() {
+        for digit in 0..16 {
+            let mut processor = processor(&[0x6000 | digit, 0xF029]);
+            steps(&mut processor, 2);
+
+            let address = digit as usize * 5;
+            assert_eq!(processor.index(), address);
+            assert!(address + 5 <= FONT_SET.len());
+        }
+    }
+
+    #[test]
+    fn font_character_masks_the_digit_and_draws_a_5_row_glyph() {
+        // 0x1A is drawn as the glyph for A at (0, 0)
+        let mut processor = processor(&[0x601A, 0xF029, 0x6100, 0xD115]);
+        steps(&mut processor, 2);
+        assert_eq!(processor.index(), 0xA * 5);
+
+        steps(&mut processor, 2);
+        let glyph_a = ["####", "#..#", "####", "#..#", "#..#"];
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&glyph_a, (0, 0)));
+    }
+
+    #[test]
+    fn run_until_draw_stops_right_after_the_first_draw() {
+        let mut processor = processor(&[0x6001, 0x6102, 0xD015, 0x6203, 0xD015]);
+
+        assert!(processor.run_until_draw(100));
+        assert_eq!(processor.pc(), 0x206);
+        assert_frames_eq(processor.framebuffer(), &from_ascii(&GLYPH_0, (1, 2)));
+    }
+
+    #[test]
+    fn run_until_draw_counts_a_clear_as_a_draw() {
+        let mut processor = processor(&[0x6001, 0x00E0, 0x6102]);
+
+        assert!(processor.run_until_draw(100));
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    #[test]
+    fn run_until_draw_gives_up_after_max_cycles() {
+        // Counts up in V0 forever without drawing
+        let mut processor = processor(&[0x7001, 0x1200]);
+
+        assert!(!processor.run_until_draw(7));
+        assert_eq!(processor.instructions(), 7);
+    }
+
+    #[test]
+    fn run_until_draw_gives_up_on_an_error() {
+        // The FX33 writes to a watchpoint before the program gets to draw
+        let mut processor = processor(&[0xA300, 0xF033, 0xD015]);
+        processor.add_watchpoint(0x300);
+
+        assert!(!processor.run_until_draw(100));
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    // Draws a 0 at (0, 0), clears the screen and draws a 0 at (8, 0), presenting every draw
+    const REDRAW: [u16; 5] = [0x6000, 0x6108, 0xD005, 0x00E0, 0xD105];
+
+    #[test]
+    fn cleared_frames_are_presented_by_default() {
+        let (mut processor, frames) = recorded_processor(&REDRAW);
+        processor.set_present_each_draw(true);
+        steps(&mut processor, 5);
+
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 3);
+        assert_frames_eq(&frames[0], &from_ascii(&GLYPH_0, (0, 0)));
+        assert_frames_eq(&frames[1], &[[0; 64]; 32]);
+        assert_frames_eq(&frames[2], &from_ascii(&GLYPH_0, (8, 0)));
+    }
+
+    #[test]
+    fn deferred_clear_keeps_the_previous_frame_until_the_next_draw() {
+        let (mut processor, frames) = recorded_processor(&REDRAW);
+        processor.set_present_each_draw(true);
+        processor.set_defer_clear(true);
+
+        // Only what's shown is deferred, the framebuffer itself is cleared
+        steps(&mut processor, 4);
+        assert_eq!(frames.borrow().len(), 1);
+        assert_frames_eq(processor.framebuffer(), &[[0; 64]; 32]);
+
+        steps(&mut processor, 1);
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 2);
+        assert_frames_eq(&frames[0], &from_ascii(&GLYPH_0, (0, 0)));
+        assert_frames_eq(&frames[1], &from_ascii(&GLYPH_0, (8, 0)));
+    }
+
+    #[test]
+    fn loop_restarts_the_hello_rom_once_it_halts() {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        processor.load_program(&crate::roms::hello_rom_bytes());
+        processor.enable_loop(time::Duration::ZERO);
+
+        for _ in 0..10 {
+            processor.tick();
+        }
+        assert_eq!(processor.pc(), 0x214);
+        assert!(processor.is_halted());
+
+        // The next pass restarts the program and executes its first instruction again
+        processor.tick();
+        assert_eq!(processor.pc(), 0x202);
+        assert_eq!(processor.instructions(), 1);
+        assert_frames_eq(processor.framebuffer(), &[[0; 64]; 32]);
+    }
+
+    #[test]
+    fn loop_waits_for_the_delay() {
+        let mut processor = processor(&[0x1200]);
+        processor.enable_loop(time::Duration::from_secs(3600));
+
+        for _ in 0..100 {
+            processor.tick();
+        }
+        assert_eq!(processor.instructions(), 100);
+    }
+
+    #[test]
+    fn programs_waiting_for_a_key_or_timer_are_not_halted() {
+        // FX0A without a key, and a loop waiting for the delay timer to run out
+        let waiting_for_key = processor(&[0xF00A]);
+        let waiting_for_timer = processor(&[0x60FF, 0xF015, 0xF007, 0x3000, 0x1204, 0x1200]);
+
+        for mut processor in [waiting_for_key, waiting_for_timer] {
+            processor.enable_loop(time::Duration::ZERO);
+            for _ in 0..1000 {
+                processor.tick();
+                assert!(!processor.is_halted());
+            }
+            assert_eq!(processor.halted_since, None);
+        }
+    }
+
+    #[test]
+    fn running_into_empty_memory_is_halted() {
+        let mut processor = processor(&[0x6001]);
+        steps(&mut processor, 1 + HALT_ZERO_RUN as usize);
+
+        assert!(processor.is_halted());
+    }
+
+    #[test]
+    fn variants_run_shifts_and_jumps_their_own_way() {
+        // V1 = 6, V0 = 1 >> 1 of VY or VX, then BNNN/BXNN to 0x300 plus V0 or V3
+        let program = [0x6001, 0x6106, 0x6310, 0x8016, 0xB300];
+
+        for (variant, shifted, jumped) in [(Variant::Vip, 3, 0x303), (Variant::Chip48, 0, 0x310)] {
+            let mut processor = processor(&program);
+            processor.set_quirks(variant.quirks());
+            steps(&mut processor, 5);
+
+            assert_eq!(processor.registers()[0], shifted, "{}", variant.name());
+            assert_eq!(processor.pc(), jumped, "{}", variant.name());
+        }
+    }
+
+    #[test]
+    fn sound_stops_on_the_tick_a_delay_set_at_the_same_time_reads_0() {
+        // With the timers ticking on every cycle, each step is one tick
+        let mut processor = processor(&[0x1200]);
+        processor.set_timer_hz(CHIP8_CLOCK_HZ);
+        processor.delay_timer = 3;
+        processor.sound_timer = 3;
+        assert!(processor.sound_playing());
+
+        for remaining in [2, 1] {
+            steps(&mut processor, 1);
+            assert_eq!(processor.delay_timer(), remaining);
+            assert!(processor.sound_playing());
+        }
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.delay_timer(), 0);
+        assert!(!processor.sound_playing());
+    }
+
+    #[test]
+    fn timers_count_down_independently() {
+        let mut processor = processor(&[0x1200]);
+        processor.set_timer_hz(CHIP8_CLOCK_HZ);
+        processor.delay_timer = 1;
+        processor.sound_timer = 4;
+
+        steps(&mut processor, 2);
+        assert_eq!(processor.delay_timer(), 0);
+        assert_eq!(processor.sound_timer(), 2);
+
+        processor.delay_timer = 5;
+        steps(&mut processor, 3);
+        assert_eq!(processor.delay_timer(), 2);
+        assert_eq!(processor.sound_timer(), 0);
+        assert!(!processor.sound_playing());
+    }
+
+    // Input with a single key press latched and nothing else going on
+    struct PressedOnce {
+        latched: Option<u8>,
+    }
+
+    impl InputBackend for PressedOnce {
+        fn last_input(&mut self) -> Option<Input> {
+            None
+        }
+
+        fn peek_latched_key(&self) -> Option<u8> {
+            self.latched
+        }
+
+        fn take_latched_key(&mut self) -> Option<u8> {
+            self.latched.take()
+        }
+    }
+
+    // Input that presses a key on a given poll, the first one being poll 1
+    struct PressedOnPoll {
+        poll: usize,
+        key: u8,
+        latched: Option<u8>,
+    }
+
+    impl InputBackend for PressedOnPoll {
+        fn last_input(&mut self) -> Option<Input> {
+            self.poll -= 1;
+            if self.poll == 0 {
+                self.latched = Some(self.key);
+            }
+            None
+        }
+
+        fn take_latched_key(&mut self) -> Option<u8> {
+            self.latched.take()
+        }
+    }
+
+    #[test]
+    fn fx0a_waits_without_changing_anything_and_counts_once_it_gets_a_key() {
+        let input = PressedOnPoll {
+            poll: 6,
+            key: 0xB,
+            latched: None,
+        };
+        let trace = SharedBuffer::default();
+        let mut processor = Processor::new(NullDisplay, input);
+        processor.load_program(&[0x6A, 0x07, 0xF3, 0x0A, 0x6B, 0x01]);
+        processor.set_trace_output(Box::new(trace.clone()));
+
+        processor.tick();
+        let registers = *processor.registers();
+
+        // Polls 2 to 5 find no key, so FX0A is retried while only time passes
+        for _ in 2..=5 {
+            processor.tick();
+            assert!(processor.waiting_for_key());
+            assert_eq!(processor.pc(), 0x202);
+            assert_eq!(*processor.registers(), registers);
+            assert_eq!(processor.instructions(), 1);
+        }
+        assert_eq!(processor.cycles(), 5);
+        assert_eq!(
+            String::from_utf8(trace.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .count(),
+            1
+        );
+
+        processor.tick();
+        assert!(!processor.waiting_for_key());
+        assert_eq!(processor.registers()[3], 0xB);
+        assert_eq!(processor.registers()[0xA], 0x07);
+        assert_eq!(processor.pc(), 0x204);
+        assert_eq!(processor.instructions(), 2);
+
+        let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+        assert_eq!(trace.lines().count(), 2);
+        assert!(trace.lines().nth(1).unwrap().contains("F30A"));
+    }
+
+    #[test]
+    fn a_latched_press_satisfies_a_single_fx0a() {
+        let mut processor = Processor::new(NullDisplay, PressedOnce { latched: Some(0x5) });
+        processor.load_program(&[0xF0, 0x0A, 0xF1, 0x0A]);
+
+        for _ in 0..10 {
+            processor.tick();
+        }
+        assert_eq!(processor.registers()[0], 0x5);
+        assert_eq!(processor.registers()[1], 0);
+        assert_eq!(processor.pc(), 0x202);
+        assert!(processor.waiting_for_key());
+    }
+
+    // Runs a drawing program for a second, each cycle finishing at now(due time), and counts the frames presented
+    fn presented_frames(now: fn(time::Instant) -> time::Instant) -> usize {
+        let (mut processor, frames) = recorded_processor(&[0xD005, 0x1200]);
+        let cycle = time::Duration::from_millis(1000 / CHIP8_CLOCK_HZ as u64);
+        let mut next_cycle = time::Instant::now();
+
+        for _ in 0..CHIP8_CLOCK_HZ {
+            next_cycle += cycle * processor.tick();
+            let finished = now(next_cycle);
+            processor.keep_pace(&mut next_cycle, finished);
+        }
+
+        let presented = frames.borrow().len();
+        presented
+    }
+
+    #[test]
+    fn frames_are_presented_while_keeping_up() {
+        let early = |due| due - time::Duration::from_millis(1);
+
+        assert_eq!(presented_frames(early), 60);
+    }
+
+    #[test]
+    fn frames_are_skipped_up_to_max_frameskip_while_behind() {
+        // Two frames are skipped for every one presented
+        let late = |due| due + time::Duration::from_millis(20);
+
+        assert_eq!(presented_frames(late), 20);
+    }
+
+    #[test]
+    fn frames_are_presented_again_once_catching_up_is_given_up() {
+        let hopelessly_late = |due| due + MAX_LAG * 2;
+
+        assert_eq!(presented_frames(hopelessly_late), 60);
+    }
+
+    // A trace output the test can still read after handing it to the processor
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_lines_show_the_operands_at_execution_time() {
+        let mut processor = processor(&[0x600C, 0x6108, 0xA22A, 0xD015, 0x7001, 0xD015]);
+        let buffer = SharedBuffer::default();
+        processor.set_trace_output(Box::new(buffer.clone()));
+        steps(&mut processor, 6);
+
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines[3], "0206 D015 DRW V0, V1, 5 ; V0=0C V1=08 I=22A");
+        assert_eq!(lines[5], "020A D015 DRW V0, V1, 5 ; V0=0D V1=08 I=22A");
+
+        // Trace files can be compared against again
+        let entries = crate::trace::parse_trace(&trace).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(
+            entries[3],
+            TraceEntry {
+                pc: 0x206,
+                opcode: 0xD015
+            }
+        );
+    }
+
+    // Stores to 0x300 over and over, on the 2nd, 3rd, 5th, 6th, ... instruction
+    const STORE_LOOP: [u16; 4] = [0xA300, 0xF033, 0xF033, 0x1202];
+
+    #[test]
+    fn fill_sets_the_range_and_leaves_the_rest() {
+        let mut processor = processor(&[0x1200]);
+        processor.add_watchpoint(0x300);
+
+        processor.run_debug_command(Command::Fill(0x300..0x310, 0xAB));
+        assert!(processor.memory()[0x300..0x310]
+            .iter()
+            .all(|&byte| byte == 0xAB));
+        assert_eq!(processor.memory()[0x2FF], 0);
+        assert_eq!(processor.memory()[0x310], 0);
+
+        // Filling isn't a write by the program, so it doesn't halt on the watchpoint
+        assert!(processor.step(None).is_ok());
+    }
+
+    #[test]
+    fn filling_the_vip_display_memory_draws_to_the_display() {
+        let mut processor = processor(&[0x1200]);
+        processor.quirks.vip_memory = true;
+
+        processor.run_debug_command(Command::Fill(0xF00..0x1000, 0xFF));
+        assert_frames_eq(processor.framebuffer(), &[[1; 64]; 32]);
+    }
+
+    #[test]
+    fn commands_are_run_within_one_poll_interval() {
+        let mut processor = processor(&STORE_LOOP);
+        let (commands, lines) = std::sync::mpsc::channel();
+        processor.console = Some(Console::from_lines(lines));
+        processor.set_debug_poll_interval(4);
+
+        // The first pass checks for commands, the next three don't
+        processor.tick();
+        commands.send(String::from("watch 300")).unwrap();
+        for _ in 0..3 {
+            processor.tick();
+            assert!(!processor.paused);
+        }
+
+        processor.tick();
+        assert!(processor.paused);
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    #[test]
+    fn watchpoints_halt_right_away_however_rarely_commands_are_polled() {
+        let mut processor = processor(&STORE_LOOP);
+        processor.set_debug_poll_interval(0xFFFF);
+        processor.add_watchpoint(0x300);
+
+        processor.tick();
+        assert!(!processor.paused);
+        processor.tick();
+        assert!(processor.paused);
+    }
+
+    // Keeps whether the beep was asked to play on each update
+    #[derive(Clone, Default)]
+    struct RecordingAudio {
+        beeping: Rc<RefCell<Vec<bool>>>,
+    }
+
+    impl AudioBackend for RecordingAudio {
+        fn set_beeping(&mut self, beeping: bool) {
+            self.beeping.borrow_mut().push(beeping);
+        }
+    }
+
+    // Input from a window in the background
+    struct Unfocused;
+
+    impl InputBackend for Unfocused {
+        fn last_input(&mut self) -> Option<Input> {
+            None
+        }
+
+        fn has_focus(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn beep_stops_while_paused_and_resumes_after() {
+        let mut processor = processor(&[0x1200]);
+        let audio = RecordingAudio::default();
+        processor.set_audio_driver(audio.clone());
+        processor.sound_timer = 100;
+
+        processor.tick();
+        processor.paused = true;
+        processor.tick();
+        processor.tick();
+        processor.paused = false;
+        processor.tick();
+
+        assert_eq!(*audio.beeping.borrow(), [true, false, false, true]);
+        // The sound timer is frozen while paused
+        assert!(processor.sound_timer() > 90);
+    }
+
+    #[test]
+    fn beep_stops_when_the_sound_timer_runs_out() {
+        let mut processor = processor(&[0x1200]);
+        let audio = RecordingAudio::default();
+        processor.set_audio_driver(audio.clone());
+        processor.set_timer_hz(CHIP8_CLOCK_HZ);
+        processor.sound_timer = 2;
+
+        for _ in 0..3 {
+            processor.tick();
+        }
+        assert_eq!(*audio.beeping.borrow(), [true, false, false]);
+    }
+
+    #[test]
+    fn beep_is_silent_in_the_background() {
+        let mut processor = Processor::new(NullDisplay, Unfocused);
+        processor.load_program(&[0x12, 0x00]);
+        let audio = RecordingAudio::default();
+        processor.set_audio_driver(audio.clone());
+        processor.sound_timer = 100;
+
+        processor.tick();
+        assert_eq!(*audio.beeping.borrow(), [false]);
+    }
+
+    #[test]
+    fn entry_point_needs_room_for_a_whole_instruction() {
+        let mut processor = processor(&[]);
+
+        for address in [0xFFF, 0x1000, usize::MAX] {
+            assert_eq!(
+                processor.set_entry_point(address),
+                Err(CpuError::EntryPointOutOfRange { address })
+            );
+        }
+        assert_eq!(processor.pc(), 0x200);
+
+        assert_eq!(processor.set_entry_point(0xFFE), Ok(()));
+        assert_eq!(processor.pc(), 0xFFE);
+        processor.reset();
+        assert_eq!(processor.pc(), 0xFFE);
+    }
+
+    #[test]
+    fn execution_starts_at_the_entry_point() {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        processor.load_program(&[0x60, 0x01, 0x60, 0x02]);
+        processor.set_entry_point(0x202).unwrap();
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.registers()[0], 2);
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    // A DXY0 at 0x200 with VF set beforehand, seeing whether the draw clears it
+    fn empty_sprite_processor(policy: EmptySpritePolicy) -> Processor {
+        let mut processor = processor(&[0xD010, 0x6001]);
+        processor.set_empty_sprite_policy(policy);
+        processor.var_registers[0xF] = 1;
+        processor
+    }
+
+    #[test]
+    fn empty_sprite_is_ignored_by_default_in_every_variant() {
+        for &variant in Variant::all() {
+            let mut processor = empty_sprite_processor(EmptySpritePolicy::Ignore);
+            processor.set_quirks(variant.quirks());
+
+            // With the display wait quirk the draw first waits for the vertical blank
+            while processor.pc() == 0x200 {
+                steps(&mut processor, 1);
+            }
+            assert_eq!(
+                processor.framebuffer(),
+                &[[0; 64]; 32],
+                "{}",
+                variant.name()
+            );
+            assert_eq!(processor.registers()[0xF], 0);
+            assert!(processor.reported_faults.is_empty());
+        }
+    }
+
+    #[test]
+    fn empty_sprite_warning_is_reported_once_without_halting() {
+        let mut processor = empty_sprite_processor(EmptySpritePolicy::Warn);
+
+        steps(&mut processor, 1);
+        assert_eq!(processor.registers()[0xF], 0);
+        assert!(processor.reported_faults.contains(&0x200));
+        steps(&mut processor, 1);
+        assert_eq!(processor.registers()[0], 1);
+    }
+
+    #[test]
+    fn empty_sprite_error_halts_after_the_draw() {
+        let mut processor = empty_sprite_processor(EmptySpritePolicy::Error);
+
+        assert_eq!(
+            processor.step(None).unwrap_err(),
+            CpuError::EmptySprite { pc: 0x200 }
+        );
+        assert_eq!(processor.pc(), 0x202);
+        assert_eq!(processor.registers()[0xF], 0);
+    }
+
+    // Pretends to save every draw as an image, like a frame dump writing every frame
+    #[derive(Default)]
+    struct SavingDisplay {
+        draws: usize,
+        saved_frame: Option<std::path::PathBuf>,
+    }
+
+    impl DisplayBackend for SavingDisplay {
+        fn draw(&mut self, _pixels: &Frame) {
+            self.saved_frame = Some(format!("frames/frame_{:06}.png", self.draws).into());
+            self.draws += 1;
+        }
+
+        fn take_saved_frame(&mut self) -> Option<std::path::PathBuf> {
+            self.saved_frame.take()
+        }
+    }
+
+    #[test]
+    fn saved_frames_are_reported_as_events() {
+        let events = SharedBuffer::default();
+        let mut processor = Processor::new(SavingDisplay::default(), NullInput);
+        processor.set_event_output(Box::new(events.clone()));
+
+        processor.toggle_pixel(0, 0);
+        processor.toggle_pixel(0, 0);
+
+        let events = String::from_utf8(events.0.borrow().clone()).unwrap();
+        assert_eq!(
+            events,
+            concat!(
+                "{\"event\":\"screenshot_saved\",\"path\":\"frames/frame_000000.png\"}\n",
+                "{\"event\":\"screenshot_saved\",\"path\":\"frames/frame_000001.png\"}\n",
+            )
+        );
+    }
+
+    // Counts the frames that ended and the draws, to check how often each happens
+    #[derive(Default)]
+    struct FrameCountingDisplay {
+        draws: Rc<RefCell<usize>>,
+        ended_frames: Rc<RefCell<usize>>,
+    }
+
+    impl DisplayBackend for FrameCountingDisplay {
+        fn draw(&mut self, _pixels: &Frame) {
+            *self.draws.borrow_mut() += 1;
+        }
+
+        fn frame_ended(&mut self) {
+            *self.ended_frames.borrow_mut() += 1;
+        }
+    }
+
+    #[test]
+    fn frames_end_once_per_frame_however_often_the_display_is_drawn() {
+        let display = FrameCountingDisplay::default();
+        let (draws, ended_frames) = (Rc::clone(&display.draws), Rc::clone(&display.ended_frames));
+
+        // Draws on every cycle, so with each draw presented there are many draws per frame
+        let mut processor = Processor::new(display, NullInput);
+        processor.load_program(&[0xD0, 0x05, 0x12, 0x00]);
+        processor.set_present_each_draw(true);
+        for _ in 0..CHIP8_CLOCK_HZ {
+            processor.tick();
+        }
+        processor.toggle_pixel(0, 0);
+
+        assert_eq!(*ended_frames.borrow(), 60);
+        assert!(*draws.borrow() > 60);
+    }
+
+    #[test]
+    fn busy_frames_slow_down_the_next_frame_but_not_the_timers() {
+        // 16 draws, then a jump to itself. At 5Hz a frame is 40 cycles, so all the draws land in the first one
+        let mut ops = vec![0xD005; 16];
+        ops.push(0x1220);
+        let mut processor = processor(&ops);
+        processor.quirks.display_wait = false;
+        processor.set_refresh_hz(5);
+        processor.enable_busy_slowdown();
+
+        steps(&mut processor, 40);
+        assert_eq!(processor.cycles(), 40);
+
+        // Each instruction of the next frame takes a cycle more, the timers keep ticking on cycles at timer_hz
+        processor.delay_timer = 0xFF;
+        steps(&mut processor, 1);
+        assert_eq!(processor.cycles(), 42);
+
+        steps(&mut processor, 19);
+        assert_eq!(processor.cycles(), 80);
+        // 40 cycles at 60Hz of the 200Hz clock
+        assert_eq!(processor.delay_timer(), 0xFF - 12);
+
+        // The slow frame didn't draw, so the one after it runs at full speed again
+        steps(&mut processor, 1);
+        assert_eq!(processor.cycles(), 81);
+    }
 }
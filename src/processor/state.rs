@@ -0,0 +1,408 @@
+use std::{
+    error, fmt, fs,
+    io::{self, Read},
+};
+
+use super::{Processor, CHIP8_CLOCK_HZ};
+use crate::CHIP8_MEMORY;
+
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 2;
+
+/*
+    Version 2 of the state layout, every field one after the other with multi-byte values big endian:
+
+        magic        4 bytes    "C8ST"
+        version      1 byte     2
+        ram          4096 bytes
+        display      2048 bytes One per pixel, row by row, each 0 or 1
+        stack        32 bytes   16 return addresses of 2 bytes
+        registers    16 bytes   V0 to VF
+        pc           2 bytes
+        index        2 bytes
+        sp           1 byte
+        delay timer  1 byte
+        sound timer  1 byte
+        timer phase  4 bytes
+        frame phase  4 bytes
+        cycles       8 bytes    Cycles executed since the program was loaded
+        instructions 8 bytes    Instructions executed since the program was loaded
+
+    Version 1 had no magic, started with the version byte and ended after the frame phase. It's still loaded, with
+    the cycle and instruction counts starting over from 0. New fields go at the end with a new version, so that
+    every older version can be migrated by filling in defaults for what it lacks. States from newer versions are
+    rejected, since there's no telling what they hold.
+
+    The configuration (quirks, timer rate, ...) isn't part of the state, it's up to whoever loads the state.
+*/
+const MACHINE_SIZE: usize = CHIP8_MEMORY + 64 * 32 + 16 * 2 + 16 + 2 + 2 + 1 + 1 + 1 + 4 + 4;
+const STATE_V1_SIZE: usize = 1 + MACHINE_SIZE;
+const STATE_SIZE: usize = STATE_MAGIC.len() + 1 + MACHINE_SIZE + 8 + 8;
+
+#[derive(Debug)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion { version: u8 },
+    WrongSize { size: usize, expected: usize },
+    Invalid { reason: &'static str },
+    Io { path: String, source: io::Error },
+    UrlNotSupported { url: String },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion { version } => write!(
+                f,
+                "State version {} is not supported, this version of the emulator reads versions 1 to {}",
+                version, STATE_VERSION
+            ),
+            StateError::WrongSize { size, expected } => {
+                write!(f, "State is {} bytes; expected {}", size, expected)
+            }
+            StateError::Invalid { reason } => write!(f, "State is invalid: {}", reason),
+            StateError::Io { path, source } => {
+                write!(f, "Could not access state {}: {}", path, source)
+            }
+            StateError::UrlNotSupported { url } => write!(
+                f,
+                "Could not load state {}: states can't be loaded from URLs, download it and load the file",
+                url
+            ),
+        }
+    }
+}
+
+impl error::Error for StateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Processor {
+    // Captures the machine state so it can be restored later with load_state_from_bytes
+    pub fn save_state_to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATE_SIZE);
+
+        bytes.extend_from_slice(STATE_MAGIC);
+        bytes.push(STATE_VERSION);
+        bytes.extend_from_slice(&self.ram);
+        bytes.extend(self.display.iter().flatten());
+        bytes.extend(
+            self.stack
+                .iter()
+                .flat_map(|&address| (address as u16).to_be_bytes()),
+        );
+        bytes.extend_from_slice(&self.var_registers);
+        bytes.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        bytes.extend_from_slice(&(self.index_register as u16).to_be_bytes());
+        bytes.push(self.sp as u8);
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend_from_slice(&self.timer_phase.to_be_bytes());
+        bytes.extend_from_slice(&self.frame_phase.to_be_bytes());
+        bytes.extend_from_slice(&self.cycle_count.to_be_bytes());
+        bytes.extend_from_slice(&self.instruction_count.to_be_bytes());
+
+        bytes
+    }
+
+    /*
+       Restores a state captured by save_state_to_bytes, by this or an older version of the emulator. The whole
+       state is checked before anything is applied, so a state that is rejected leaves the processor untouched.
+    */
+    pub fn load_state_from_bytes(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let (version, fields) = match bytes.strip_prefix(STATE_MAGIC) {
+            Some(rest) => match rest.split_first() {
+                Some((&version, fields)) => (version, fields),
+                None => return Err(StateError::NotAState),
+            },
+            None if bytes.first() == Some(&1) => (1, &bytes[1..]),
+            None => return Err(StateError::NotAState),
+        };
+
+        let expected = match version {
+            1 => STATE_V1_SIZE,
+            STATE_VERSION => STATE_SIZE,
+            _ => return Err(StateError::UnsupportedVersion { version }),
+        };
+
+        if bytes.len() != expected {
+            return Err(StateError::WrongSize {
+                size: bytes.len(),
+                expected,
+            });
+        }
+
+        let mut reader = Reader { bytes: fields };
+
+        let mut ram = [0; CHIP8_MEMORY];
+        ram.copy_from_slice(reader.take(CHIP8_MEMORY));
+
+        let mut display = [[0; 64]; 32];
+        for row in display.iter_mut() {
+            row.copy_from_slice(reader.take(64));
+        }
+        if display.iter().flatten().any(|&pixel| pixel > 1) {
+            return Err(StateError::Invalid {
+                reason: "pixels must be 0 or 1",
+            });
+        }
+
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16() as usize;
+        }
+
+        let mut var_registers = [0; 16];
+        var_registers.copy_from_slice(reader.take(16));
+
+        let pc = reader.u16() as usize;
+        let index_register = reader.u16() as usize;
+        let sp = reader.u8() as usize;
+        let delay_timer = reader.u8();
+        let sound_timer = reader.u8();
+        let timer_phase = reader.u32();
+        let frame_phase = reader.u32();
+
+        // Version 1 didn't keep count, so the counts start over
+        let (cycle_count, instruction_count) = match version {
+            1 => (0, 0),
+            _ => (reader.u64(), reader.u64()),
+        };
+
+        if pc >= CHIP8_MEMORY
+            || index_register >= CHIP8_MEMORY
+            || sp > stack.len()
+            || stack.iter().any(|&address| address >= CHIP8_MEMORY)
+        {
+            return Err(StateError::Invalid {
+                reason: "PC, I, the stack pointer or a return address is out of range",
+            });
+        }
+
+        // The phases count cycles towards the next tick or frame and are always below the clock rate
+        if timer_phase >= CHIP8_CLOCK_HZ || frame_phase >= CHIP8_CLOCK_HZ {
+            return Err(StateError::Invalid {
+                reason: "the timer or frame phase is out of range",
+            });
+        }
+
+        self.ram = ram;
+        self.display = display;
+        self.stack = stack;
+        self.var_registers = var_registers;
+        self.pc = pc;
+        self.index_register = index_register;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.timer_phase = timer_phase;
+        self.frame_phase = frame_phase;
+        self.cycle_count = cycle_count;
+        self.instruction_count = instruction_count;
+        self.display_dirty = true;
+
+        Ok(())
+    }
+
+    // Loads a state from a file, or from stdin when the path is -. There's no HTTP client, so URLs are rejected.
+    pub fn load_state(&mut self, path: &str) -> Result<(), StateError> {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            return Err(StateError::UrlNotSupported {
+                url: String::from(path),
+            });
+        }
+
+        let io_error = |source| StateError::Io {
+            path: String::from(path),
+            source,
+        };
+
+        let bytes = if path == "-" {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes).map_err(io_error)?;
+            bytes
+        } else {
+            fs::read(path).map_err(io_error)?
+        };
+
+        self.load_state_from_bytes(&bytes)
+    }
+
+    pub fn save_state(&self, path: &str) -> Result<(), StateError> {
+        fs::write(path, self.save_state_to_bytes()).map_err(|source| StateError::Io {
+            path: String::from(path),
+            source,
+        })
+    }
+}
+
+// Reads fields off the front of a state whose size was already checked
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let (field, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        field
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.u8(), self.u8()])
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_be_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    fn u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8));
+        u64::from_be_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::{NullDisplay, NullInput};
+
+    const STACK_OFFSET: usize = STATE_MAGIC.len() + 1 + CHIP8_MEMORY + 64 * 32;
+
+    // A processor partway through a program that called a subroutine and drew
+    fn running_processor() -> Processor {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        processor.load_program(&[0x60, 0x05, 0x22, 0x06, 0x00, 0x00, 0xD0, 0x05, 0x12, 0x08]);
+        for _ in 0..3 {
+            processor.step(None).unwrap();
+        }
+
+        processor
+    }
+
+    #[test]
+    fn state_loads_from_a_byte_buffer() {
+        let processor = running_processor();
+        let bytes = processor.save_state_to_bytes();
+        assert_eq!(bytes.len(), STATE_SIZE);
+
+        let mut loaded = Processor::new(NullDisplay, NullInput);
+        loaded.load_state_from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.save_state_to_bytes(), bytes);
+        assert_eq!(loaded.pc(), 0x208);
+        assert_eq!(loaded.framebuffer(), processor.framebuffer());
+        assert_eq!(loaded.instructions(), 3);
+    }
+
+    #[test]
+    fn out_of_range_return_address_is_rejected_without_touching_the_processor() {
+        let mut bytes = running_processor().save_state_to_bytes();
+        // A return address left above the stack pointer still has to be in memory
+        bytes[STACK_OFFSET + 2..STACK_OFFSET + 4].copy_from_slice(&0x1000u16.to_be_bytes());
+
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        let before = processor.save_state_to_bytes();
+
+        assert!(matches!(
+            processor.load_state_from_bytes(&bytes),
+            Err(StateError::Invalid { .. })
+        ));
+        assert_eq!(processor.save_state_to_bytes(), before);
+    }
+
+    #[test]
+    fn truncated_and_foreign_states_are_rejected() {
+        let bytes = running_processor().save_state_to_bytes();
+        let mut processor = Processor::new(NullDisplay, NullInput);
+
+        assert!(matches!(
+            processor.load_state_from_bytes(&bytes[..bytes.len() - 1]),
+            Err(StateError::WrongSize { size, expected: STATE_SIZE }) if size == STATE_SIZE - 1
+        ));
+        assert!(matches!(
+            processor.load_state_from_bytes(b"PNG"),
+            Err(StateError::NotAState)
+        ));
+        assert!(matches!(
+            processor.load_state_from_bytes(&[]),
+            Err(StateError::NotAState)
+        ));
+    }
+
+    #[test]
+    fn urls_are_rejected_with_a_clear_message() {
+        let mut processor = Processor::new(NullDisplay, NullInput);
+        let err = processor
+            .load_state("https://example.com/game.state")
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Could not load state https://example.com/game.state: states can't be loaded from URLs, download it \
+             and load the file"
+        );
+    }
+
+    const PHASES_OFFSET: usize = STACK_OFFSET + 16 * 2 + 16 + 2 + 2 + 1 + 1 + 1;
+
+    #[test]
+    fn version_1_states_load_with_the_counts_starting_over() {
+        let processor = running_processor();
+        let bytes = processor.save_state_to_bytes();
+
+        // Version 1 is the version byte and the machine, without the magic and the counts
+        let mut v1 = vec![1];
+        v1.extend_from_slice(&bytes[STATE_MAGIC.len() + 1..STATE_MAGIC.len() + 1 + MACHINE_SIZE]);
+        assert_eq!(v1.len(), STATE_V1_SIZE);
+
+        let mut loaded = Processor::new(NullDisplay, NullInput);
+        loaded.load_state_from_bytes(&v1).unwrap();
+
+        assert_eq!(loaded.pc(), processor.pc());
+        assert_eq!(loaded.memory()[..], processor.memory()[..]);
+        assert_eq!(loaded.registers(), processor.registers());
+        assert_eq!(loaded.cycles(), 0);
+        assert_eq!(loaded.instructions(), 0);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut bytes = running_processor().save_state_to_bytes();
+        bytes[STATE_MAGIC.len()] = STATE_VERSION + 1;
+
+        let err = Processor::new(NullDisplay, NullInput)
+            .load_state_from_bytes(&bytes)
+            .unwrap_err();
+
+        assert!(matches!(err, StateError::UnsupportedVersion { version: 3 }));
+        assert_eq!(
+            err.to_string(),
+            "State version 3 is not supported, this version of the emulator reads versions 1 to 2"
+        );
+    }
+
+    #[test]
+    fn out_of_range_phases_are_rejected() {
+        for offset in [PHASES_OFFSET, PHASES_OFFSET + 4] {
+            let mut bytes = running_processor().save_state_to_bytes();
+            bytes[offset..offset + 4].copy_from_slice(&CHIP8_CLOCK_HZ.to_be_bytes());
+
+            assert!(matches!(
+                Processor::new(NullDisplay, NullInput).load_state_from_bytes(&bytes),
+                Err(StateError::Invalid { .. })
+            ));
+        }
+    }
+}
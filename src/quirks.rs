@@ -0,0 +1,205 @@
+/*
+    Behaviors that differ between CHIP-8 interpreters. ROMs written for one interpreter can misbehave on another,
+    so each is configurable. The defaults match the original COSMAC VIP interpreter, except that draws don't wait
+    for the vertical blank since that noticeably slows most games down, and sprites wrap around the edges of the
+    display like they always have in this emulator.
+
+    The VIP memory quirk is off by default as well. On a 4K VIP the top of memory held the interpreter's stack and
+    variables (0xEA0-0xEFF) and the display (0xF00-0xFFF, one bit per pixel), so a ROM writing there corrupted them
+    or drew on the screen. With the quirk on, reading or writing display RAM goes to the display and writes to the
+    interpreter's area are reported as faults but still happen. This applies to every instruction accessing memory
+    (DXYN, FX33, FX55 and FX65), and FX55 and FX65 also leave I pointing past the last register they stored or
+    loaded, like the VIP interpreter did. Out of scope: the stack still lives outside of memory, so writes there
+    don't corrupt it, and the first 512 bytes don't hold the interpreter.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool, // 8XY6/8XYE copy VY into VX before shifting. CHIP-48 and SUPER-CHIP shift VX in place
+    pub jump_uses_vx: bool, // BXNN jumps to XNN + VX like CHIP-48 and SUPER-CHIP, instead of BNNN to NNN + V0
+    pub display_wait: bool, // DXYN waits for the next 60Hz vertical blank, limiting draws to one per frame (VIP)
+    pub clip_sprites: bool, // Sprite pixels past the right or bottom edge are dropped (VIP) instead of wrapping
+    pub vip_memory: bool, // The top of memory is mapped like on a 4K VIP, and FX55/FX65 advance I like the VIP
+}
+
+pub const QUIRK_NAMES: [&str; 5] = [
+    "shift-vy",
+    "jump-vx",
+    "display-wait",
+    "clip-sprites",
+    "vip-memory",
+];
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            display_wait: false,
+            clip_sprites: false,
+            vip_memory: false,
+        }
+    }
+}
+
+impl Quirks {
+    // Returns a copy of these quirks with the named quirk flipped
+    pub fn toggled(&self, name: &str) -> Result<Self, String> {
+        let mut quirks = *self;
+
+        match name {
+            "shift-vy" => quirks.shift_uses_vy = !quirks.shift_uses_vy,
+            "jump-vx" => quirks.jump_uses_vx = !quirks.jump_uses_vx,
+            "display-wait" => quirks.display_wait = !quirks.display_wait,
+            "clip-sprites" => quirks.clip_sprites = !quirks.clip_sprites,
+            "vip-memory" => quirks.vip_memory = !quirks.vip_memory,
+            _ => {
+                return Err(format!(
+                    "Unknown quirk {}, expected one of: {}",
+                    name,
+                    QUIRK_NAMES.join(", ")
+                ))
+            }
+        }
+
+        Ok(quirks)
+    }
+}
+
+// Interpreters whose quirks can be picked as a whole, instead of one by one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Chip8,
+    Vip,
+    Chip48,
+}
+
+impl Variant {
+    pub fn all() -> &'static [Variant] {
+        &[Variant::Chip8, Variant::Vip, Variant::Chip48]
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match Variant::all().iter().find(|variant| variant.name() == name) {
+            Some(&variant) => Ok(variant),
+            None => {
+                let names: Vec<&str> = Variant::all().iter().map(Variant::name).collect();
+                Err(format!(
+                    "Unknown variant {}, expected one of: {}",
+                    name,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip-8",
+            Variant::Vip => "vip",
+            Variant::Chip48 => "chip-48",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => {
+                "The defaults: COSMAC VIP behavior, but draws don't wait and sprites wrap"
+            }
+            Variant::Vip => {
+                "The original COSMAC VIP interpreter, as faithfully as this emulator can"
+            }
+            Variant::Chip48 => {
+                "CHIP-48 on the HP-48, which SUPER-CHIP and most modern ROMs build on"
+            }
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::default(),
+            Variant::Vip => Quirks {
+                display_wait: true,
+                clip_sprites: true,
+                vip_memory: true,
+                ..Quirks::default()
+            },
+            Variant::Chip48 => Quirks {
+                shift_uses_vy: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                ..Quirks::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_are_found_by_name() {
+        for &variant in Variant::all() {
+            assert_eq!(Variant::from_name(variant.name()), Ok(variant));
+        }
+        assert_eq!(
+            Variant::from_name("schip"),
+            Err(String::from(
+                "Unknown variant schip, expected one of: chip-8, vip, chip-48"
+            ))
+        );
+    }
+
+    #[test]
+    fn variant_profiles() {
+        assert_eq!(Variant::Chip8.quirks(), Quirks::default());
+        assert_eq!(
+            Variant::Vip.quirks(),
+            Quirks {
+                shift_uses_vy: true,
+                jump_uses_vx: false,
+                display_wait: true,
+                clip_sprites: true,
+                vip_memory: true,
+            }
+        );
+        assert_eq!(
+            Variant::Chip48.quirks(),
+            Quirks {
+                shift_uses_vy: false,
+                jump_uses_vx: true,
+                display_wait: false,
+                clip_sprites: true,
+                vip_memory: false,
+            }
+        );
+    }
+
+    #[test]
+    fn every_quirk_toggles_on_its_own_and_back() {
+        let quirks = Quirks::default();
+        let mut toggled: Vec<Quirks> = Vec::new();
+
+        for name in QUIRK_NAMES {
+            let once = quirks.toggled(name).unwrap();
+            assert_ne!(once, quirks, "{}", name);
+            assert!(
+                !toggled.contains(&once),
+                "{} flips the same quirk as another",
+                name
+            );
+            assert_eq!(once.toggled(name), Ok(quirks), "{}", name);
+            toggled.push(once);
+        }
+    }
+
+    #[test]
+    fn unknown_quirks_are_rejected() {
+        assert_eq!(
+            Quirks::default().toggled("wrap"),
+            Err(String::from(
+                "Unknown quirk wrap, expected one of: shift-vy, jump-vx, display-wait, clip-sprites, vip-memory"
+            ))
+        );
+    }
+}
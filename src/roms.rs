@@ -0,0 +1,29 @@
+/*
+    Small ROMs assembled in code, so that tests, examples and first runs don't depend on external ROM files whose
+    origin and license are uncertain.
+*/
+
+// Address of the halt loop at the end of the hello ROM
+pub const HELLO_ROM_HALT_ADDRESS: usize = 0x214;
+
+/*
+    Clears the screen, draws "C8" in the middle of it using the built in font and then halts by jumping to itself.
+    The glyphs are 5 rows tall and start at (28, 13) and (33, 13).
+*/
+pub fn hello_rom_bytes() -> Vec<u8> {
+    let opcodes: [u16; 11] = [
+        0x00E0, // 200: Clear the screen
+        0x601C, // 202: V0 = 28, x of the first glyph
+        0x610D, // 204: V1 = 13, y of both glyphs
+        0x620C, // 206: V2 = 0xC
+        0xF229, // 208: I = font glyph for V2
+        0xD015, // 20A: Draw it at (V0, V1)
+        0x7005, // 20C: Move one glyph (and a column of spacing) right
+        0x6208, // 20E: V2 = 0x8
+        0xF229, // 210: I = font glyph for V2
+        0xD015, // 212: Draw it at (V0, V1)
+        0x1214, // 214: Halt by jumping to self
+    ];
+
+    opcodes.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
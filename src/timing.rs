@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+const WINDOW_FRAMES: u32 = 120; // Two seconds at 60Hz
+
+/*
+    Collects how long frames take in real time and prints a summary line every WINDOW_FRAMES frames, e.g.
+
+        timing frames=120 min_ms=14.98 avg_ms=16.71 max_ms=35.02 missed=1
+
+    A frame counts as missed when it took more than one and a half frame periods, i.e. long enough that a display
+    refresh was skipped. The line is meant to be pasted into bug reports and parsed with simple tools, so its fields
+    stay key=value pairs separated by spaces.
+*/
+pub struct TimingStats {
+    period: Duration, // How long a frame should take
+    last_frame: Option<Instant>,
+    frames: u32,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    missed: u32,
+}
+
+impl TimingStats {
+    pub fn new(refresh_hz: u32) -> Self {
+        TimingStats {
+            period: Duration::from_secs(1) / refresh_hz,
+            last_frame: None,
+            frames: 0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            total: Duration::ZERO,
+            missed: 0,
+        }
+    }
+
+    // Called at the end of every frame
+    pub fn frame_ended(&mut self) {
+        let now = Instant::now();
+        let Some(last_frame) = self.last_frame.replace(now) else {
+            return;
+        };
+
+        let duration = now - last_frame;
+        self.frames += 1;
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+        self.total += duration;
+
+        if duration > self.period * 3 / 2 {
+            self.missed += 1;
+        }
+
+        if self.frames == WINDOW_FRAMES {
+            self.print();
+            self.restart();
+        }
+    }
+
+    // Starts a new window, e.g. after being paused so the pause isn't counted as a slow frame
+    pub fn restart(&mut self) {
+        self.last_frame = None;
+        self.frames = 0;
+        self.min = Duration::MAX;
+        self.max = Duration::ZERO;
+        self.total = Duration::ZERO;
+        self.missed = 0;
+    }
+
+    fn print(&self) {
+        println!(
+            "timing frames={} min_ms={:.2} avg_ms={:.2} max_ms={:.2} missed={}",
+            self.frames,
+            millis(self.min),
+            millis(self.total / self.frames),
+            millis(self.max),
+            self.missed
+        );
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,64 @@
+/*
+    Runs corax89's chip8-test-rom (https://github.com/corax89/chip8-test-rom), which is in roms/test_opcode.ch8,
+    headless and checks that every opcode test passed. The ROM draws a grid of 18 results, three per row, each the
+    name of the opcodes under test followed by OK or NO. Every result has to read OK, so one that reads NO, is
+    blank or was drawn elsewhere fails the test.
+*/
+
+use chip_eight::drivers::{NullDisplay, NullInput, Rom};
+use chip_eight::frames;
+use chip_eight::processor::Processor;
+
+const ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms/test_opcode.ch8");
+const CYCLES: usize = 2000; // The ROM is done drawing after about 500 and then loops forever
+
+const RESULT_COLUMNS: [usize; 3] = [10, 32, 52]; // Where the OK or NO of each column of results starts
+const RESULT_ROWS: usize = 6;
+const ROW_HEIGHT: usize = 5;
+
+#[rustfmt::skip]
+const OK: [&str; 4] = [
+    "###.#.#",
+    "#.#.##.",
+    "#.#.#.#",
+    "###.#.#",
+];
+
+#[test]
+fn every_opcode_test_passes() {
+    let rom = Rom::new(ROM_PATH).unwrap();
+    let mut processor = Processor::new(NullDisplay, NullInput);
+    processor.load_program(rom.bytes());
+
+    for _ in 0..CYCLES {
+        processor
+            .step(None)
+            .expect("Unknown opcodes are skipped by default");
+    }
+
+    let screen = frames::to_ascii(processor.framebuffer());
+    let lines: Vec<&str> = screen.lines().collect();
+
+    let mut failed = Vec::new();
+    for row in 0..RESULT_ROWS {
+        let top = 1 + row * ROW_HEIGHT;
+
+        for &left in &RESULT_COLUMNS {
+            let passed = OK
+                .iter()
+                .enumerate()
+                .all(|(i, ok)| lines[top + i][left..].starts_with(ok));
+
+            if !passed {
+                failed.push(format!("row {}, column {}", row + 1, left));
+            }
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "Opcode tests failed at {}:\n{}",
+        failed.join("; "),
+        screen
+    );
+}